@@ -1,14 +1,14 @@
 //! source files
 
 use std::{
+    cell::Cell,
     collections::HashMap,
     ffi::{OsStr, OsString},
     fmt::Debug,
     num::NonZeroU32,
     ops::Range,
     path::{Path, PathBuf},
-    rc::Rc,
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 
 use crate::util;
@@ -30,14 +30,26 @@ pub struct Source {
     /// The index of each newline character, for efficiently fetching lines
     /// (for error reporting, e.g.)
     line_offsets: Arc<[usize]>,
+    /// A stable hash of `contents`' raw bytes, computed once up front so
+    /// later rebuilds can cheaply tell whether this source changed.
+    content_hash: u64,
 }
 
+/// The default maximum depth of a chain of nested `include` statements.
+///
+/// This only needs to be large enough to cover legitimate deeply-nested
+/// projects; its real purpose is to turn a runaway (or cyclic, should a
+/// cycle somehow slip past [`SourceList::source_for_path`]'s own check)
+/// include chain into a clear error instead of a stack overflow.
+const DEFAULT_MAX_INCLUDE_DEPTH: usize = 50;
+
 /// A list of sources in a project.
 #[derive(Clone, Debug)]
 pub struct SourceList {
-    resolver: Rc<dyn SourceResolver>,
+    resolver: Arc<dyn SourceResolver>,
     ids: HashMap<OsString, FileId>,
     sources: HashMap<FileId, Source>,
+    max_include_depth: usize,
 }
 
 /// A map from positions in a resolved token tree (which may contain the
@@ -53,7 +65,7 @@ pub struct SourceMap {
 #[error("Failed to load source at '{}': '{cause}'", Path::new(.path.as_os_str()).display())]
 pub struct SourceLoadError {
     #[source]
-    cause: Rc<dyn std::error::Error>,
+    cause: Arc<dyn std::error::Error + Send + Sync>,
     path: OsString,
 }
 
@@ -67,7 +79,11 @@ pub struct SourceLoadError {
 /// If you need a custom resolver, you can either implement this trait for some
 /// custom type, or you can use a closure with the signature,
 /// `|&OsStr| -> Result<String, SourceLoadError>`.
-pub trait SourceResolver {
+///
+/// Resolvers must be `Send + Sync`: `SourceList::resolve_all` resolves each
+/// of its roots on its own thread, sharing a single resolver between those
+/// threads.
+pub trait SourceResolver: Send + Sync {
     /// Return the contents of the utf-8 encoded file at the provided path.
     fn get_contents(&self, path: &OsStr) -> Result<String, SourceLoadError>;
 
@@ -100,6 +116,19 @@ pub trait SourceResolver {
         Ok(Source::new(path.to_owned(), contents.into()))
     }
 
+    /// Return, in order, every candidate path that should be tried for an
+    /// `include($path)`.
+    ///
+    /// The default implementation tries only the result of
+    /// [`Self::resolve_raw_path`]. A resolver that can search multiple
+    /// directories (such as [`FileSystemResolver`]) can override this to
+    /// offer further fallback locations; [`SourceList::source_for_path`]
+    /// tries each candidate in turn and, if none resolve, reports every one
+    /// of them in the resulting [`SourceLoadError`].
+    fn candidate_paths(&self, path: &OsStr, included_from: Option<&OsStr>) -> Vec<OsString> {
+        vec![self.resolve_raw_path(path, included_from)]
+    }
+
     // a little helper used in our debug impl
     #[doc(hidden)]
     fn type_name(&self) -> &'static str {
@@ -115,7 +144,7 @@ impl std::fmt::Debug for dyn SourceResolver {
 
 impl<F> SourceResolver for F
 where
-    F: Fn(&OsStr) -> Result<String, SourceLoadError>,
+    F: Fn(&OsStr) -> Result<String, SourceLoadError> + Send + Sync,
 {
     fn get_contents(&self, path: &OsStr) -> Result<String, SourceLoadError> {
         (self)(path)
@@ -127,11 +156,28 @@ where
 /// This is the common case.
 pub(crate) struct FileSystemResolver {
     project_root: PathBuf,
+    /// Extra directories searched, in order, for an include that isn't found
+    /// relative to the project root or the including file.
+    search_paths: Vec<PathBuf>,
 }
 
 impl FileSystemResolver {
     pub(crate) fn new(project_root: PathBuf) -> Self {
-        Self { project_root }
+        Self {
+            project_root,
+            search_paths: Vec::new(),
+        }
+    }
+
+    /// Add a list of directories to search, in order, when an include can't
+    /// be resolved relative to the project root or the including file.
+    ///
+    /// This lets a project split shared feature files across several
+    /// directories without every `include` needing to spell out an absolute
+    /// or root-relative path.
+    pub(crate) fn with_search_paths(mut self, search_paths: Vec<PathBuf>) -> Self {
+        self.search_paths = search_paths;
+        self
     }
 }
 
@@ -151,6 +197,20 @@ impl SourceResolver for FileSystemResolver {
             .map_err(|io_err| SourceLoadError::new(path.into(), io_err))
             .map(PathBuf::into_os_string)
     }
+
+    fn candidate_paths(&self, path: &OsStr, included_from: Option<&OsStr>) -> Vec<OsString> {
+        let mut candidates = vec![self.resolve_raw_path(path, included_from)];
+        // an absolute include path isn't subject to search-path fallback:
+        // there's only one file it could possibly mean.
+        if !self.search_paths.is_empty() && !Path::new(path).is_absolute() {
+            candidates.extend(
+                self.search_paths
+                    .iter()
+                    .map(|dir| dir.join(path).into_os_string()),
+            );
+        }
+        candidates
+    }
 }
 
 impl FileId {
@@ -167,11 +227,16 @@ impl FileId {
 impl Source {
     pub(crate) fn new(path: impl Into<OsString>, contents: Arc<str>) -> Self {
         let line_offsets = line_offsets(&contents);
+        // computed over the raw bytes, before any newline/line-offset
+        // processing, so it's sensitive to nothing but genuine content
+        // changes
+        let content_hash = content_hash(contents.as_bytes());
         Source {
             path: path.into(),
             id: FileId::next(),
             contents,
             line_offsets,
+            content_hash,
         }
     }
 
@@ -180,6 +245,15 @@ impl Source {
         &self.contents
     }
 
+    /// A stable hash of this source's raw contents.
+    ///
+    /// Two `Source`s with identical content hash identically, regardless of
+    /// path; this is used by [`SourceList::fingerprint`] to detect unchanged
+    /// files across rebuilds.
+    pub fn content_hash(&self) -> u64 {
+        self.content_hash
+    }
+
     /// The source's path.
     ///
     /// If the source is a file, this will be the *resolved* file path. In other
@@ -243,6 +317,18 @@ fn line_offsets(text: &str) -> Arc<[usize]> {
     result.into()
 }
 
+/// Hash raw file bytes for [`Source::content_hash`].
+///
+/// `DefaultHasher`'s algorithm isn't guaranteed stable across Rust versions,
+/// but it's deterministic within a build, which is all a single fingerprint
+/// file needs to be useful between runs of the same toolchain.
+fn content_hash(bytes: &[u8]) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
 impl SourceMap {
     pub(crate) fn add_entry(&mut self, src: Range<usize>, dest: (FileId, usize)) {
         if !src.is_empty() {
@@ -250,20 +336,54 @@ impl SourceMap {
         }
     }
 
-    /// panics if `global_range` crosses a file barrier?
-    pub(crate) fn resolve_range(&self, global_range: Range<usize>) -> (FileId, Range<usize>) {
-        // it is hard to imagine more than a couple hundred include statements,
-        // and even that would be extremely rare, so I don't think it's really
-        // worth doing a binary search here?
-        let (chunk, (file, local_offset)) = self
+    /// Resolve a range in the combined token stream to the per-file
+    /// sub-ranges it overlaps, in order.
+    ///
+    /// A range produced from content assembled out of multiple `include`d
+    /// files can cross a file barrier, in which case more than one entry is
+    /// returned; most callers only care about the common case of a range
+    /// contained in a single file, for which [`Self::resolve_range_single`]
+    /// is more convenient.
+    pub(crate) fn resolve_range(&self, global_range: Range<usize>) -> Vec<(FileId, Range<usize>)> {
+        if global_range.is_empty() {
+            return Vec::new();
+        }
+
+        // `offsets` is built by `add_entry` calls in increasing order of
+        // `src`, so a binary search on the end of each chunk finds the first
+        // chunk that could possibly overlap `global_range`.
+        let start_idx = self
             .offsets
+            .partition_point(|(chunk, _)| chunk.end <= global_range.start);
+
+        self.offsets[start_idx..]
             .iter()
-            .find(|item| item.0.contains(&global_range.start))
-            .unwrap();
-        let chunk_offset = global_range.start - chunk.start;
-        let range_start = *local_offset + chunk_offset;
-        let len = global_range.end - global_range.start;
-        (*file, range_start..range_start + len)
+            .take_while(|(chunk, _)| chunk.start < global_range.end)
+            .filter_map(|(chunk, (file, local_offset))| {
+                let overlap_start = chunk.start.max(global_range.start);
+                let overlap_end = chunk.end.min(global_range.end);
+                (overlap_start < overlap_end).then(|| {
+                    let range_start = local_offset + (overlap_start - chunk.start);
+                    let range_end = range_start + (overlap_end - overlap_start);
+                    (*file, range_start..range_end)
+                })
+            })
+            .collect()
+    }
+
+    /// Convenience for the common case where a range is known not to cross
+    /// an include boundary.
+    ///
+    /// Panics if `global_range` is empty or spans more than one file; use
+    /// [`Self::resolve_range`] when that's a possibility.
+    pub(crate) fn resolve_range_single(&self, global_range: Range<usize>) -> (FileId, Range<usize>) {
+        let mut chunks = self.resolve_range(global_range);
+        assert_eq!(
+            chunks.len(),
+            1,
+            "range {global_range:?} does not resolve to exactly one file"
+        );
+        chunks.pop().unwrap()
     }
 }
 
@@ -272,10 +392,17 @@ impl SourceList {
         SourceList {
             ids: Default::default(),
             sources: Default::default(),
-            resolver: Rc::new(resolver),
+            resolver: Arc::new(resolver),
+            max_include_depth: DEFAULT_MAX_INCLUDE_DEPTH,
         }
     }
 
+    /// Override the default maximum include depth (50).
+    pub(crate) fn with_max_include_depth(mut self, max_include_depth: usize) -> Self {
+        self.max_include_depth = max_include_depth;
+        self
+    }
+
     pub(crate) fn get(&self, id: &FileId) -> Option<&Source> {
         self.sources.get(id)
     }
@@ -288,33 +415,666 @@ impl SourceList {
     /// is the literal (e.g. unresolved and uncanonicalized) `$path` in the
     /// include.
     ///
-    /// If the source cannot be resolved, returns an error.
+    /// `include_chain` is the stack of files, from the root source down to
+    /// (and including) the file containing this `include` statement, that
+    /// led here; pass an empty slice when loading the root source itself.
+    /// This is used to reject both a chain deeper than the configured
+    /// maximum and an include that would resolve back to a file already on
+    /// the chain.
+    ///
+    /// If the source cannot be resolved, returns an error. For a resolver
+    /// that offers more than one [candidate path][SourceResolver::candidate_paths]
+    /// (such as [`FileSystemResolver`] configured with search paths), the
+    /// error reports every directory that was tried.
     pub(crate) fn source_for_path(
         &mut self,
         path: &dyn AsRef<OsStr>,
-        included_by: Option<FileId>,
+        include_chain: &[FileId],
     ) -> Result<FileId, SourceLoadError> {
-        let included_by = included_by.map(|id| self.sources.get(&id).unwrap().path.as_os_str());
-        let path = self.resolver.resolve_raw_path(path.as_ref(), included_by);
-        let canonical = self.resolver.canonicalize(&path)?;
+        if include_chain.len() >= self.max_include_depth {
+            return Err(SourceLoadError::max_include_depth_exceeded(
+                path.as_ref().to_owned(),
+                self.max_include_depth,
+            ));
+        }
+
+        let included_by = include_chain
+            .last()
+            .map(|id| self.sources.get(id).unwrap().path.as_os_str());
+        let candidates = self.resolver.candidate_paths(path.as_ref(), included_by);
+
+        for candidate in &candidates {
+            let Ok(canonical) = self.resolver.canonicalize(candidate) else {
+                continue;
+            };
+
+            if let Some(&existing) = self.ids.get(&canonical) {
+                if let Some(cycle_start) = include_chain.iter().position(|&id| id == existing) {
+                    return Err(self.cyclic_include_error(&include_chain[cycle_start..], existing));
+                }
+                return Ok(existing);
+            }
+
+            if let Ok(source) = self.resolver.resolve(candidate) {
+                let id = source.id;
+                self.ids.insert(canonical, id);
+                self.sources.insert(id, source);
+                return Ok(id);
+            }
+        }
+
+        Err(SourceLoadError::not_found(path.as_ref().to_owned(), candidates))
+    }
+
+    /// Build the error for an include that would resolve back to a file
+    /// already on `cycle`, naming every file from there back to `repeated`.
+    fn cyclic_include_error(&self, cycle: &[FileId], repeated: FileId) -> SourceLoadError {
+        let mut chain: Vec<OsString> = cycle
+            .iter()
+            .map(|id| self.sources.get(id).unwrap().path.clone())
+            .collect();
+        chain.push(self.sources.get(&repeated).unwrap().path.clone());
+        SourceLoadError::cyclic_include(chain)
+    }
+
+    /// Resolve every path in `roots` in parallel, each on its own thread,
+    /// funneling successfully-loaded sources through the existing
+    /// canonical-path dedup map.
+    ///
+    /// This only parallelizes the *independent* work of reading and
+    /// canonicalizing each root; the shared `ids`/`sources` maps are updated
+    /// under a lock, held only long enough to check for (or record) a
+    /// canonical path, not across any filesystem I/O. If two roots
+    /// canonicalize to the same path, both are read, but only the first to
+    /// finish is kept - callers should pass independent roots (e.g. the
+    /// distinct top-level `include`s of a file) to get real parallelism out
+    /// of this.
+    ///
+    /// Returns the `FileId` of every root on success, in the same order as
+    /// `roots`, or every `SourceLoadError` encountered if any root failed.
+    pub(crate) fn resolve_all(
+        &mut self,
+        roots: &[PathBuf],
+    ) -> Result<Vec<FileId>, Vec<SourceLoadError>> {
+        let state = Mutex::new(&mut *self);
+        let results: Vec<Result<FileId, SourceLoadError>> = std::thread::scope(|scope| {
+            roots
+                .iter()
+                .map(|root| {
+                    let state = &state;
+                    scope.spawn(move || Self::resolve_one(state, root))
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|handle| handle.join().expect("resolve_all worker panicked"))
+                .collect()
+        });
+
+        let mut ids = Vec::with_capacity(results.len());
+        let mut errors = Vec::new();
+        for result in results {
+            match result {
+                Ok(id) => ids.push(id),
+                Err(err) => errors.push(err),
+            }
+        }
+        if errors.is_empty() {
+            Ok(ids)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Worker body for a single root in [`Self::resolve_all`].
+    fn resolve_one(state: &Mutex<&mut SourceList>, root: &Path) -> Result<FileId, SourceLoadError> {
+        let resolver = Arc::clone(&state.lock().unwrap().resolver);
+        let path = root.as_os_str();
+        let candidates = resolver.candidate_paths(path, None);
+
+        let mut last_err = None;
+        for candidate in &candidates {
+            let canonical = match resolver.canonicalize(candidate) {
+                Ok(canonical) => canonical,
+                Err(e) => {
+                    last_err = Some(e);
+                    continue;
+                }
+            };
+
+            if let Some(&id) = state.lock().unwrap().ids.get(&canonical) {
+                return Ok(id);
+            }
+
+            match resolver.resolve(candidate) {
+                Ok(source) => {
+                    let mut list = state.lock().unwrap();
+                    // another thread may have already loaded this canonical
+                    // path while we were reading it off the lock; prefer
+                    // whichever FileId got there first.
+                    let id = *list.ids.entry(canonical).or_insert(source.id);
+                    list.sources.entry(id).or_insert(source);
+                    return Ok(id);
+                }
+                Err(e) => last_err = Some(e),
+            }
+        }
+
+        Err(last_err
+            .unwrap_or_else(|| SourceLoadError::not_found(path.to_owned(), candidates)))
+    }
+
+    /// A caching view over this list's sources for repeated line/column
+    /// lookups, such as when emitting many diagnostics over the same files.
+    pub(crate) fn caching_view(&self) -> CachingSourceMapView<'_> {
+        CachingSourceMapView::new(&self.sources)
+    }
+
+    /// Snapshot the content hash of every currently loaded source, keyed by
+    /// canonical path, so it can be persisted and compared against on a
+    /// later run via [`Self::changed_since`].
+    pub(crate) fn fingerprint(&self) -> SourceFingerprint {
+        let mut entries: Vec<(OsString, u64)> = self
+            .ids
+            .iter()
+            .map(|(canonical, id)| {
+                (canonical.clone(), self.sources.get(id).unwrap().content_hash())
+            })
+            .collect();
+        entries.sort();
+        SourceFingerprint { entries }
+    }
+
+    /// Compare the currently loaded sources against a fingerprint taken on a
+    /// previous run, returning the `FileId` of every currently loaded source
+    /// whose canonical path is new or whose content hash differs.
+    ///
+    /// A caller can use this to skip re-parsing (and reuse a cached token
+    /// tree for) every file that isn't returned here.
+    pub(crate) fn changed_since(&self, previous: &SourceFingerprint) -> Vec<FileId> {
+        let previous_hashes: HashMap<&OsStr, u64> = previous
+            .entries
+            .iter()
+            .map(|(path, hash)| (path.as_os_str(), *hash))
+            .collect();
+
+        self.ids
+            .iter()
+            .filter(|(canonical, id)| {
+                let hash = self.sources.get(id).unwrap().content_hash();
+                previous_hashes.get(canonical.as_os_str()) != Some(&hash)
+            })
+            .map(|(_, id)| *id)
+            .collect()
+    }
+}
+
+/// A caching view over a [`SourceList`]'s sources for line/column lookups.
+///
+/// Modeled on rustc's caching source-map view: remembers the last resolved
+/// line and, for a new query, first checks whether the offset falls inside
+/// that line (an O(1) hit) before falling back to `Source`'s own binary
+/// search. A miss also probes the adjacent line before giving up, since
+/// diagnostic spans tend to be contiguous.
+pub(crate) struct CachingSourceMapView<'a> {
+    sources: &'a HashMap<FileId, Source>,
+    last: Cell<Option<CachedLine>>,
+}
+
+#[derive(Clone, Copy)]
+struct CachedLine {
+    file: FileId,
+    /// 0-indexed.
+    line_index: usize,
+    /// Half-open byte range of this line, including its trailing newline.
+    start: usize,
+    end: usize,
+}
+
+impl<'a> CachingSourceMapView<'a> {
+    fn new(sources: &'a HashMap<FileId, Source>) -> Self {
+        Self {
+            sources,
+            last: Cell::new(None),
+        }
+    }
+
+    /// Compute the (1-indexed line, 0-indexed column) for `offset` in `file`.
+    pub(crate) fn line_col(&self, file: FileId, offset: usize) -> (usize, usize) {
+        let source = self.sources.get(&file).expect("unknown FileId");
+
+        if let Some(cached) = self.last.get().filter(|c| c.file == file) {
+            if (cached.start..cached.end).contains(&offset) {
+                return (cached.line_index + 1, offset - cached.start);
+            }
+            for neighbor in [cached.line_index + 1, cached.line_index.wrapping_sub(1)] {
+                if let Some((start, end)) = line_bounds(source, neighbor) {
+                    if (start..end).contains(&offset) {
+                        self.last.set(Some(CachedLine {
+                            file,
+                            line_index: neighbor,
+                            start,
+                            end,
+                        }));
+                        return (neighbor + 1, offset - start);
+                    }
+                }
+            }
+        }
+
+        let (line_number, column) = source.line_col_for_offset(offset);
+        let line_index = line_number - 1;
+        if let Some((start, end)) = line_bounds(source, line_index) {
+            self.last.set(Some(CachedLine {
+                file,
+                line_index,
+                start,
+                end,
+            }));
+        }
+        (line_number, column)
+    }
+
+    /// Compute the 1-indexed lines spanned by `range` in `file`, as a
+    /// half-open range whose end is one past the last line.
+    pub(crate) fn span_to_lines(&self, file: FileId, range: Range<usize>) -> Range<usize> {
+        let start_line = self.line_col(file, range.start).0;
+        let last_offset = range.end.saturating_sub(1).max(range.start);
+        let end_line = self.line_col(file, last_offset).0;
+        start_line..end_line + 1
+    }
+}
+
+fn line_bounds(source: &Source, line_index: usize) -> Option<(usize, usize)> {
+    let start = *source.line_offsets.get(line_index)?;
+    let end = source
+        .line_offsets
+        .get(line_index + 1)
+        .copied()
+        .unwrap_or(source.contents.len());
+    Some((start, end))
+}
+
+/// A snapshot of the content hash of every source loaded into a
+/// [`SourceList`], keyed by canonical path, taken via
+/// [`SourceList::fingerprint`].
+///
+/// This is the serializable half of the incremental-rebuild story: a build
+/// tool persists it between runs and feeds the previous run's value into
+/// [`SourceList::changed_since`] to find out what actually needs re-parsing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceFingerprint {
+    /// Sorted by canonical path, so two fingerprints of the same sources
+    /// compare and print identically regardless of load order.
+    entries: Vec<(OsString, u64)>,
+}
+
+impl SourceFingerprint {
+    /// The `(canonical_path, content_hash)` pairs in this fingerprint,
+    /// sorted by path.
+    pub fn entries(&self) -> &[(OsString, u64)] {
+        &self.entries
+    }
+}
 
-        if let Some(src) = self.ids.get(&canonical) {
-            return Ok(*src);
+impl std::fmt::Display for SourceFingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (path, hash) in &self.entries {
+            writeln!(f, "{hash:016x} {}", Path::new(path).display())?;
         }
+        Ok(())
+    }
+}
+
+/// An error produced when parsing a [`SourceFingerprint`] from a malformed
+/// manifest.
+#[derive(Clone, Debug, thiserror::Error)]
+#[error("malformed source fingerprint entry: '{0}'")]
+pub struct ParseFingerprintError(String);
+
+impl std::str::FromStr for SourceFingerprint {
+    type Err = ParseFingerprintError;
 
-        let source = self.resolver.resolve(&path)?;
-        let id = source.id;
-        self.ids.insert(canonical, id);
-        self.sources.insert(id, source);
-        Ok(id)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut entries = Vec::new();
+        for line in s.lines() {
+            let (hash, path) = line
+                .split_once(' ')
+                .ok_or_else(|| ParseFingerprintError(line.to_owned()))?;
+            let hash = u64::from_str_radix(hash, 16)
+                .map_err(|_| ParseFingerprintError(line.to_owned()))?;
+            entries.push((OsString::from(path), hash));
+        }
+        entries.sort();
+        Ok(SourceFingerprint { entries })
     }
 }
 
 impl SourceLoadError {
-    pub(crate) fn new(path: OsString, cause: impl std::error::Error + 'static) -> Self {
+    pub(crate) fn new(path: OsString, cause: impl std::error::Error + Send + Sync + 'static) -> Self {
         Self {
-            cause: Rc::new(cause),
+            cause: Arc::new(cause),
             path,
         }
     }
+
+    /// Construct an error reporting that `path` could not be found in any of
+    /// the `tried` candidate locations.
+    pub(crate) fn not_found(path: OsString, tried: Vec<OsString>) -> Self {
+        Self::new(path, NotFoundInSearchPaths(tried))
+    }
+
+    /// Construct an error reporting a cyclic include, naming every file from
+    /// the start of the cycle back to the file that would re-include it.
+    pub(crate) fn cyclic_include(chain: Vec<OsString>) -> Self {
+        let path = chain.last().cloned().unwrap_or_default();
+        Self::new(path, CyclicInclude(chain))
+    }
+
+    /// Construct an error reporting that resolving `path` would exceed the
+    /// configured maximum include depth.
+    pub(crate) fn max_include_depth_exceeded(path: OsString, max_depth: usize) -> Self {
+        Self::new(path, MaxIncludeDepthExceeded(max_depth))
+    }
+}
+
+/// The cause used by [`SourceLoadError::not_found`], listing every location
+/// that was searched.
+#[derive(Debug)]
+struct NotFoundInSearchPaths(Vec<OsString>);
+
+impl std::fmt::Display for NotFoundInSearchPaths {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "not found in any of: ")?;
+        for (i, tried) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "'{}'", Path::new(tried).display())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for NotFoundInSearchPaths {}
+
+/// The cause used by [`SourceLoadError::cyclic_include`], naming every file
+/// on the cycle in inclusion order, ending with the file that repeats.
+#[derive(Debug)]
+struct CyclicInclude(Vec<OsString>);
+
+impl std::fmt::Display for CyclicInclude {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cyclic include: ")?;
+        for (i, path) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " -> ")?;
+            }
+            write!(f, "'{}'", Path::new(path).display())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CyclicInclude {}
+
+/// The cause used by [`SourceLoadError::max_include_depth_exceeded`].
+#[derive(Debug)]
+struct MaxIncludeDepthExceeded(usize);
+
+impl std::fmt::Display for MaxIncludeDepthExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "maximum include depth ({}) exceeded", self.0)
+    }
+}
+
+impl std::error::Error for MaxIncludeDepthExceeded {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An in-memory [`SourceResolver`] over a fixed set of paths, for testing
+    /// `SourceList` without touching the filesystem.
+    #[derive(Clone)]
+    struct MapResolver(HashMap<OsString, String>);
+
+    impl MapResolver {
+        fn new<'a>(files: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+            Self(
+                files
+                    .into_iter()
+                    .map(|(path, contents)| (OsString::from(path), contents.to_owned()))
+                    .collect(),
+            )
+        }
+    }
+
+    impl SourceResolver for MapResolver {
+        fn get_contents(&self, path: &OsStr) -> Result<String, SourceLoadError> {
+            self.0
+                .get(path)
+                .cloned()
+                .ok_or_else(|| SourceLoadError::not_found(path.to_owned(), vec![path.to_owned()]))
+        }
+    }
+
+    #[test]
+    fn cyclic_include_is_rejected() {
+        let mut list = SourceList::new(MapResolver::new([
+            ("a.fea", "include(b.fea);"),
+            ("b.fea", "include(a.fea);"),
+        ]));
+
+        let id_a = list.source_for_path(&"a.fea", &[]).unwrap();
+        let id_b = list.source_for_path(&"b.fea", &[id_a]).unwrap();
+
+        let err = list
+            .source_for_path(&"a.fea", &[id_a, id_b])
+            .expect_err("a.fea including itself (via b.fea) should be rejected");
+        assert!(err.to_string().contains("cyclic include"));
+    }
+
+    #[test]
+    fn max_include_depth_is_enforced() {
+        let mut list = SourceList::new(MapResolver::new([
+            ("deep.fea", ""),
+            ("a.fea", ""),
+            ("b.fea", ""),
+        ]))
+        .with_max_include_depth(3);
+
+        // a chain exactly at the limit is rejected; the ids don't need to
+        // correspond to real sources, since the depth check happens before
+        // any of them are looked up.
+        let chain = [FileId::next(), FileId::next(), FileId::next()];
+        let err = list
+            .source_for_path(&"deep.fea", &chain)
+            .expect_err("include chain at max depth should be rejected");
+        assert!(err.to_string().contains("maximum include depth"));
+
+        // one shallower succeeds. Below the limit the including file (the
+        // last id on the chain) is looked up, so this chain is made of real
+        // sources.
+        let id_a = list.source_for_path(&"a.fea", &[]).unwrap();
+        let id_b = list.source_for_path(&"b.fea", &[id_a]).unwrap();
+        let shallow_chain = [id_a, id_b];
+        assert!(list.source_for_path(&"deep.fea", &shallow_chain).is_ok());
+    }
+
+    /// A directory under the system temp dir that removes itself on drop,
+    /// for tests that need `FileSystemResolver` to see real files on disk.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            use std::sync::atomic::{AtomicU32, Ordering};
+            static COUNTER: AtomicU32 = AtomicU32::new(0);
+            let unique = COUNTER.fetch_add(1, Ordering::Relaxed);
+            let path = std::env::temp_dir().join(format!(
+                "fea-rs-source-test-{name}-{}-{unique}",
+                std::process::id()
+            ));
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        fn write(&self, name: &str, contents: &str) -> PathBuf {
+            let path = self.0.join(name);
+            std::fs::write(&path, contents).unwrap();
+            path
+        }
+
+        fn path(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn search_paths_are_only_a_fallback() {
+        let root = TempDir::new("root");
+        let search = TempDir::new("search");
+
+        // present in both the project root and a search path: the
+        // root-relative candidate must win.
+        root.write("shared.fea", "relative wins");
+        search.write("shared.fea", "search wins");
+        // present only in the search path.
+        search.write("extra.fea", "from search");
+
+        let resolver = FileSystemResolver::new(root.path().to_owned())
+            .with_search_paths(vec![search.path().to_owned()]);
+        let mut list = SourceList::new(resolver);
+
+        let shared_id = list.source_for_path(&"shared.fea", &[]).unwrap();
+        assert_eq!(list.get(&shared_id).unwrap().text(), "relative wins");
+
+        let extra_id = list.source_for_path(&"extra.fea", &[]).unwrap();
+        assert_eq!(list.get(&extra_id).unwrap().text(), "from search");
+
+        let err = list
+            .source_for_path(&"missing.fea", &[])
+            .expect_err("missing.fea doesn't exist anywhere");
+        let message = err.to_string();
+        assert!(message.contains(&root.path().join("missing.fea").display().to_string()));
+        assert!(message.contains(&search.path().join("missing.fea").display().to_string()));
+    }
+
+    #[test]
+    fn fingerprint_detects_changed_and_new_files() {
+        let mut before = SourceList::new(MapResolver::new([
+            ("a.fea", "contents of a"),
+            ("b.fea", "contents of b"),
+        ]));
+        before.source_for_path(&"a.fea", &[]).unwrap();
+        before.source_for_path(&"b.fea", &[]).unwrap();
+        let fingerprint = before.fingerprint();
+
+        // a fingerprint round-trips through its text representation.
+        let round_tripped: SourceFingerprint = fingerprint.to_string().parse().unwrap();
+        assert_eq!(round_tripped, fingerprint);
+
+        // on the next run, `a.fea` is unchanged, `b.fea`'s contents changed,
+        // and `c.fea` is new.
+        let mut after = SourceList::new(MapResolver::new([
+            ("a.fea", "contents of a"),
+            ("b.fea", "contents of b, but different"),
+            ("c.fea", "contents of c"),
+        ]));
+        let id_a = after.source_for_path(&"a.fea", &[]).unwrap();
+        let id_b = after.source_for_path(&"b.fea", &[]).unwrap();
+        let id_c = after.source_for_path(&"c.fea", &[]).unwrap();
+
+        let changed = after.changed_since(&fingerprint);
+        assert!(!changed.contains(&id_a), "a.fea did not change");
+        assert!(changed.contains(&id_b), "b.fea's contents changed");
+        assert!(changed.contains(&id_c), "c.fea is new");
+    }
+
+    #[test]
+    fn caching_view_agrees_with_cold_path() {
+        let contents = "line one\nline two\nline three\nline four\nline five\n";
+        let mut list = SourceList::new(MapResolver::new([("multi.fea", contents)]));
+        let id = list.source_for_path(&"multi.fea", &[]).unwrap();
+        let source = list.get(&id).unwrap().clone();
+
+        let view = list.caching_view();
+
+        // query offsets out of order, including a jump that misses both the
+        // cached line and its probed neighbors, to exercise the cache-hit,
+        // neighbor-probe, and full-binary-search paths against the same
+        // offsets `Source::line_col_for_offset` would resolve directly.
+        let offsets = [0, 5, 9, 14, 30, 2, 45];
+        for offset in offsets {
+            assert_eq!(
+                view.line_col(id, offset),
+                source.line_col_for_offset(offset),
+                "mismatch at offset {offset}"
+            );
+        }
+    }
+
+    fn two_file_source_map() -> (SourceMap, FileId, FileId) {
+        let file_a = FileId::next();
+        let file_b = FileId::next();
+
+        let mut map = SourceMap::default();
+        // the combined token stream is [0, 10) from file_a, immediately
+        // followed by [10, 20) from file_b.
+        map.add_entry(0..10, (file_a, 100));
+        map.add_entry(10..20, (file_b, 0));
+        (map, file_a, file_b)
+    }
+
+    #[test]
+    fn resolve_range_splits_across_include_boundary() {
+        let (map, file_a, file_b) = two_file_source_map();
+
+        // entirely within file_a: resolve_range_single should work.
+        assert_eq!(map.resolve_range_single(2..8), (file_a, 102..108));
+
+        // crosses the boundary at offset 10: must split into both files'
+        // sub-ranges instead of panicking.
+        let chunks = map.resolve_range(5..15);
+        assert_eq!(chunks, vec![(file_a, 105..110), (file_b, 0..5)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "does not resolve to exactly one file")]
+    fn resolve_range_single_rejects_cross_file_range() {
+        let (map, _, _) = two_file_source_map();
+        map.resolve_range_single(5..15);
+    }
+
+    #[test]
+    fn resolve_all_matches_sequential_resolution() {
+        let roots: Vec<PathBuf> = vec!["a.fea".into(), "b.fea".into(), "c.fea".into()];
+        let resolver = MapResolver::new([
+            ("a.fea", "contents of a"),
+            ("b.fea", "contents of b"),
+            ("c.fea", "contents of c"),
+        ]);
+
+        let mut sequential = SourceList::new(resolver.clone());
+        let sequential_ids: Vec<FileId> = roots
+            .iter()
+            .map(|root| sequential.source_for_path(root, &[]).unwrap())
+            .collect();
+
+        let mut parallel = SourceList::new(resolver);
+        let parallel_ids = parallel.resolve_all(&roots).unwrap();
+
+        assert_eq!(sequential_ids.len(), parallel_ids.len());
+        for (seq_id, par_id) in sequential_ids.iter().zip(&parallel_ids) {
+            let seq_source = sequential.get(seq_id).unwrap();
+            let par_source = parallel.get(par_id).unwrap();
+            assert_eq!(seq_source.path(), par_source.path());
+            assert_eq!(seq_source.text(), par_source.text());
+        }
+    }
 }
@@ -3,15 +3,12 @@
 //! This module provides utilities for building COLR v1 LayerLists that automatically
 //! deduplicate common paint subsequences, reducing table size.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-use write_fonts::tables::colr::{LayerList, Paint};
-
-/// Maximum length of subsequences to check for layer reuse.
-///
-/// This limit balances reuse opportunities against O(n²) search complexity.
-/// Larger values would increase build time with diminishing returns.
-const MAX_REUSE_LEN: usize = 32;
+use write_fonts::{
+    tables::colr::{ClipBox, ClipList, LayerList, Paint},
+    types::GlyphId,
+};
 
 /// Maximum number of layers in a single PaintColrLayers (uint8 limit).
 ///
@@ -19,37 +16,111 @@ const MAX_REUSE_LEN: usize = 32;
 /// into an n-ary tree structure.
 const MAX_PAINT_COLR_LAYER_COUNT: u8 = 255;
 
+/// Base for the polynomial rolling hash used to index paint-id windows.
+///
+/// Any large odd constant works: collisions are always resolved by a direct
+/// id comparison before a reuse is accepted, so the base only affects how
+/// often we fall into that (cheap) slow path.
+const HASH_BASE: u64 = 1_000_000_007;
+
 /// Generate all valid subsequence ranges for layer reuse.
 ///
 /// Yields (start, end) pairs where:
-/// - Length is between 2 and min(num_layers, MAX_REUSE_LEN)
+/// - Length is at least 2 (there's no upper bound: any repeated run, however
+///   long, is eligible for reuse)
 /// - End is exclusive (standard Rust range convention)
 fn reuse_ranges(num_layers: usize) -> impl Iterator<Item = (usize, usize)> {
     (0..num_layers).flat_map(move |lbound| {
         let min_ubound = lbound + 2; // Minimum length: 2
-        let max_ubound = (lbound + MAX_REUSE_LEN + 1).min(num_layers + 1);
-        (min_ubound..max_ubound).map(move |ubound| (lbound, ubound))
+        (min_ubound..=num_layers).map(move |ubound| (lbound, ubound))
     })
 }
 
+/// A prefix-hashed sequence of small integer ids, supporting O(1) hashing of
+/// any contiguous window via the standard polynomial rolling-hash identity
+/// `hash(i..j) = prefix[j] - prefix[i] * base^(j - i)`.
+#[derive(Default)]
+struct RollingHash {
+    /// `prefix[i]` is the rolling hash of `ids[0..i]`.
+    prefix: Vec<u64>,
+    /// `pow[i]` is `HASH_BASE^i`, precomputed as the sequence grows.
+    pow: Vec<u64>,
+}
+
+impl RollingHash {
+    fn new() -> Self {
+        Self {
+            prefix: vec![0],
+            pow: vec![1],
+        }
+    }
+
+    fn extend(&mut self, ids: &[u32]) {
+        for &id in ids {
+            let last_hash = *self.prefix.last().unwrap();
+            let last_pow = *self.pow.last().unwrap();
+            self.prefix
+                .push(last_hash.wrapping_mul(HASH_BASE).wrapping_add(id as u64));
+            self.pow.push(last_pow.wrapping_mul(HASH_BASE));
+        }
+    }
+
+    /// Hash of the half-open window `[start, start + len)`.
+    fn window_hash(&self, start: usize, len: usize) -> u64 {
+        let end = start + len;
+        self.prefix[end].wrapping_sub(self.prefix[start].wrapping_mul(self.pow[len]))
+    }
+}
+
 /// Cache for detecting and reusing layer subsequences.
 ///
-/// Maintains a mapping from paint subsequences to their first occurrence
-/// in the layer list, enabling deduplication by replacing duplicate sequences
-/// with PaintColrLayers references.
+/// Instead of keying a `HashMap` on cloned `Vec<Paint>` subsequences (which
+/// requires hashing and comparing potentially deep paint trees for every
+/// candidate window), every distinct `Paint` is interned to a small integer
+/// id and windows are indexed by a rolling hash over the id stream. This
+/// makes each candidate check an O(1) hash lookup plus a cheap direct
+/// id-comparison to defeat collisions, so there's no need to cap the
+/// reusable subsequence length the way `MAX_REUSE_LEN` used to.
 struct LayerReuseCache {
-    /// Maps paint subsequence → first layer index in LayerList
-    reuse_pool: HashMap<Vec<Paint>, u32>,
+    /// Maps each distinct `Paint` to a small integer id.
+    paint_ids: HashMap<Paint, u32>,
+    /// The interned id of every layer registered so far, in the same order
+    /// as `LayerListBuilder::layers`.
+    ids: Vec<u32>,
+    hashes: RollingHash,
+    /// window hash → start indices (into `ids`) of windows with that hash,
+    /// oldest first, so the first verified candidate is the first occurrence.
+    index: HashMap<u64, Vec<u32>>,
 }
 
 impl LayerReuseCache {
     /// Create a new empty LayerReuseCache.
     fn new() -> Self {
         Self {
-            reuse_pool: HashMap::new(),
+            paint_ids: HashMap::new(),
+            ids: Vec::new(),
+            hashes: RollingHash::new(),
+            index: HashMap::new(),
         }
     }
 
+    /// Intern `paint`, assigning it a fresh id the first time it's seen.
+    fn intern(&mut self, paint: &Paint) -> u32 {
+        let next_id = self.paint_ids.len() as u32;
+        *self.paint_ids.entry(paint.clone()).or_insert(next_id)
+    }
+
+    /// Look up the id of a paint we may not have seen before, without
+    /// interning it. A never-before-seen paint is given an id that can't
+    /// possibly collide with a real one, since it can't match any
+    /// previously-registered window anyway.
+    fn peek(&self, paint: &Paint) -> u32 {
+        self.paint_ids
+            .get(paint)
+            .copied()
+            .unwrap_or(u32::MAX - self.paint_ids.len() as u32)
+    }
+
     /// Attempt to find and replace reusable subsequences in the given layers.
     ///
     /// Iteratively searches for matching subsequences in the reuse pool,
@@ -59,7 +130,9 @@ impl LayerReuseCache {
     /// Returns the modified layer list with reused subsequences replaced.
     fn try_reuse(&self, mut layers: Vec<Paint>) -> Vec<Paint> {
         loop {
-            let mut found_reuse = false;
+            let candidate_ids: Vec<u32> = layers.iter().map(|p| self.peek(p)).collect();
+            let mut candidate_hashes = RollingHash::new();
+            candidate_hashes.extend(&candidate_ids);
 
             // Generate all possible subsequence ranges, sorted by priority:
             // 1. Longer sequences first (more savings)
@@ -74,24 +147,42 @@ impl LayerReuseCache {
                 )
             });
 
+            let mut found_reuse = None;
             for (lbound, ubound) in ranges {
-                let slice = &layers[lbound..ubound];
-
-                // Check if this subsequence exists in reuse pool
-                if let Some(&first_layer_index) = self.reuse_pool.get(slice) {
-                    // Replace with PaintColrLayers reference
-                    let num_layers = (ubound - lbound) as u8;
-                    let new_paint = Paint::colr_layers(num_layers, first_layer_index);
-
-                    layers.splice(lbound..ubound, std::iter::once(new_paint));
-                    found_reuse = true;
+                let len = ubound - lbound;
+                let hash = candidate_hashes.window_hash(lbound, len);
+                let Some(candidates) = self.index.get(&hash) else {
+                    continue;
+                };
+                let window = &candidate_ids[lbound..ubound];
+                if let Some(&first_layer_index) = candidates.iter().find(|&&start| {
+                    self.ids[start as usize..start as usize + len] == *window
+                }) {
+                    found_reuse = Some((lbound, ubound, first_layer_index));
                     break;
                 }
             }
 
-            if !found_reuse {
+            let Some((lbound, ubound, first_layer_index)) = found_reuse else {
                 break;
+            };
+
+            // Replace with PaintColrLayers reference(s). A single node can only
+            // address up to MAX_PAINT_COLR_LAYER_COUNT layers, so a longer
+            // reused span is split into that many chunk nodes, each pointing
+            // at a contiguous slice of the original (already-registered) run.
+            let total_len = ubound - lbound;
+            let mut replacement = Vec::new();
+            let mut offset = 0usize;
+            while offset < total_len {
+                let chunk_size = (total_len - offset).min(MAX_PAINT_COLR_LAYER_COUNT as usize);
+                replacement.push(Paint::colr_layers(
+                    chunk_size as u8,
+                    first_layer_index + offset as u32,
+                ));
+                offset += chunk_size;
             }
+            layers.splice(lbound..ubound, replacement);
         }
 
         layers
@@ -103,12 +194,18 @@ impl LayerReuseCache {
     /// * `layers` - The paint sequence to register
     /// * `first_index` - Starting index in the LayerList where these layers appear
     fn register(&mut self, layers: &[Paint], first_index: u32) {
+        debug_assert_eq!(first_index as usize, self.ids.len());
+        let new_ids: Vec<u32> = layers.iter().map(|p| self.intern(p)).collect();
+        self.ids.extend_from_slice(&new_ids);
+        self.hashes.extend(&new_ids);
+
         for (lbound, ubound) in reuse_ranges(layers.len()) {
-            let subsequence = layers[lbound..ubound].to_vec();
+            let len = ubound - lbound;
+            let hash = self
+                .hashes
+                .window_hash(first_index as usize + lbound, len);
             let abs_index = first_index + lbound as u32;
-
-            // Only insert if not already present (first occurrence wins)
-            self.reuse_pool.entry(subsequence).or_insert(abs_index);
+            self.index.entry(hash).or_default().push(abs_index);
         }
     }
 }
@@ -123,6 +220,10 @@ pub struct LayerListBuilder {
     layers: Vec<Paint>,
     /// Optional cache for detecting and reusing layer subsequences
     reuse_cache: Option<LayerReuseCache>,
+    /// Canonical paint tree → the first glyph id whose base paint had that
+    /// exact shape, for whole-tree reuse across base glyphs via
+    /// `add_base_glyph`.
+    base_glyph_paints: HashMap<Paint, GlyphId>,
 }
 
 impl LayerListBuilder {
@@ -138,7 +239,27 @@ impl LayerListBuilder {
             } else {
                 None
             },
+            base_glyph_paints: HashMap::new(),
+        }
+    }
+
+    /// Register a base glyph's root paint, returning the paint that should
+    /// actually be stored for this glyph.
+    ///
+    /// If `paint` is identical (recursively, including any `PaintColrLayers`
+    /// references produced by [`Self::add_paint_layers`]) to a paint already
+    /// registered for an earlier glyph, that duplicate tree is dropped and a
+    /// `PaintColrGlyph` pointing at the earlier glyph is returned in its
+    /// place. Otherwise `paint` is returned unchanged.
+    ///
+    /// Callers are expected to use the returned paint as the glyph's entry in
+    /// the BaseGlyphList.
+    pub fn add_base_glyph(&mut self, gid: GlyphId, paint: Paint) -> Paint {
+        if let Some(&existing_gid) = self.base_glyph_paints.get(&paint) {
+            return Paint::colr_glyph(existing_gid);
         }
+        self.base_glyph_paints.insert(paint.clone(), gid);
+        paint
     }
 
     /// Add multiple paints as layers and return a Paint that references them.
@@ -237,6 +358,15 @@ impl LayerListBuilder {
         // Otherwise, we need to recursively build another level
         let num_tree_nodes = tree_nodes.len() as u32;
         let tree_first_index = self.layers.len() as u32;
+        // These synthetic nodes occupy real slots in `self.layers`, so they
+        // must be registered too: otherwise `self.ids` falls out of step
+        // with `self.layers` and the next `register()` call's bookkeeping
+        // (which assumes `first_index == self.ids.len()`) goes wrong. As a
+        // bonus, registering them lets an identical later run collapse
+        // straight onto this tree instead of rebuilding its own copy.
+        if let Some(ref mut cache) = self.reuse_cache {
+            cache.register(&tree_nodes, tree_first_index);
+        }
         self.layers.extend(tree_nodes);
 
         // Recursively build the tree if needed
@@ -253,4 +383,225 @@ impl LayerListBuilder {
 
         Some(LayerList::new(self.layers.len() as u32, self.layers))
     }
+
+    /// Build the final LayerList together with a ClipList derived from
+    /// `clip_boxes`.
+    ///
+    /// `clip_boxes` gives a precomputed bounding box per base glyph. Adjacent
+    /// glyph ids (by id order) that share an identical box are coalesced into
+    /// a single ClipList range, which is the compact representation the COLR
+    /// spec expects.
+    pub fn build_with_cliplist(
+        self,
+        clip_boxes: &BTreeMap<GlyphId, ClipBox>,
+    ) -> (Option<LayerList>, Option<ClipList>) {
+        let clip_list = build_clip_list(clip_boxes);
+        (self.build(), clip_list)
+    }
+}
+
+/// Coalesce a per-glyph clip box map into contiguous glyph-id ranges that
+/// share an identical box, the form a ClipList stores on disk.
+fn build_clip_list(clip_boxes: &BTreeMap<GlyphId, ClipBox>) -> Option<ClipList> {
+    if clip_boxes.is_empty() {
+        return None;
+    }
+
+    let mut ranges: Vec<(GlyphId, GlyphId, ClipBox)> = Vec::new();
+    for (&gid, clip_box) in clip_boxes.iter() {
+        match ranges.last_mut() {
+            Some((_, end, last_box))
+                if *last_box == *clip_box && gid.to_u32() == end.to_u32() + 1 =>
+            {
+                *end = gid;
+            }
+            _ => ranges.push((gid, gid, clip_box.clone())),
+        }
+    }
+
+    Some(ClipList::new(ranges))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Distinct leaf paints for test fixtures. `Paint::colr_layers` is the one
+    // `Paint` constructor this module already depends on, so we reuse it here
+    // rather than reaching into unrelated paint variants just to get values
+    // that compare unequal.
+    fn leaf(id: u32) -> Paint {
+        Paint::colr_layers(1, id)
+    }
+
+    fn leaves(ids: impl IntoIterator<Item = u32>) -> Vec<Paint> {
+        ids.into_iter().map(leaf).collect()
+    }
+
+    #[test]
+    fn no_reuse_without_repeats() {
+        let mut builder = LayerListBuilder::new(true);
+        builder.add_paint_layers(leaves(0..4));
+        builder.add_paint_layers(leaves(10..14));
+        assert_eq!(builder.layers.len(), 8);
+    }
+
+    #[test]
+    fn reuses_repeated_short_run() {
+        let mut builder = LayerListBuilder::new(true);
+        builder.add_paint_layers(leaves(0..4));
+        let second = builder.add_paint_layers(leaves(0..4));
+
+        // the second, identical run should not have added any new layers
+        assert_eq!(builder.layers.len(), 4);
+        assert_eq!(second, Paint::colr_layers(4, 0));
+    }
+
+    #[test]
+    fn prefers_longest_reuse() {
+        let mut builder = LayerListBuilder::new(true);
+        builder.add_paint_layers(leaves(0..6));
+        // shares a 4-layer prefix with the first run; the longest match
+        // (the 4-layer prefix) should win over any shorter one
+        let mut second_run = leaves(0..4);
+        second_run.extend(leaves([100, 101]));
+        let second = builder.add_paint_layers(second_run);
+
+        // the reused prefix is represented by a PaintColrLayers reference
+        // node, which itself occupies a new slot in `layers` alongside the
+        // genuinely new [100, 101] tail -- three new entries in total, tied
+        // together by the returned reference.
+        assert_eq!(builder.layers.len(), 6 + 3);
+        assert_eq!(second, Paint::colr_layers(3, 6));
+    }
+
+    #[test]
+    fn reuses_runs_longer_than_old_cap() {
+        // the previous implementation capped reusable runs at 32 layers;
+        // this run is well past that, and should still be fully reused.
+        const RUN_LEN: u32 = 200;
+        let mut builder = LayerListBuilder::new(true);
+        builder.add_paint_layers(leaves(0..RUN_LEN));
+        let second = builder.add_paint_layers(leaves(0..RUN_LEN));
+
+        assert_eq!(builder.layers.len(), RUN_LEN as usize);
+        assert_eq!(second, Paint::colr_layers(RUN_LEN as u8, 0));
+    }
+
+    #[test]
+    fn reused_run_longer_than_paint_colr_layer_max_is_not_truncated() {
+        // a reused span longer than MAX_PAINT_COLR_LAYER_COUNT must be split
+        // into multiple chunk nodes rather than silently truncated to u8::MAX.
+        const RUN_LEN: u32 = 300;
+        let mut builder = LayerListBuilder::new(true);
+        let first = builder.add_paint_layers(leaves(0..RUN_LEN));
+        let second = builder.add_paint_layers(leaves(0..RUN_LEN));
+
+        // the first call needs an n-ary tree (300 > 255), appending a
+        // 255-chunk and a 45-chunk at indices 300 and 301 on top of the 300
+        // base layers.
+        assert_eq!(builder.layers.len(), RUN_LEN as usize + 2);
+        assert_eq!(
+            builder.layers[RUN_LEN as usize..],
+            [Paint::colr_layers(255, 0), Paint::colr_layers(45, 255)]
+        );
+        assert_eq!(first, Paint::colr_layers(2, RUN_LEN));
+
+        // the second, identical 300-layer run is fully reused -- including
+        // the tree nodes built for the first call, which are themselves
+        // registered for reuse -- so it collapses onto the exact same
+        // reference without adding anything new.
+        assert_eq!(second, first);
+        assert_eq!(builder.layers.len(), RUN_LEN as usize + 2);
+    }
+
+    #[test]
+    fn no_reuse_when_disabled() {
+        let mut builder = LayerListBuilder::new(false);
+        builder.add_paint_layers(leaves(0..4));
+        builder.add_paint_layers(leaves(0..4));
+
+        // with reuse disabled, the second identical run is duplicated
+        assert_eq!(builder.layers.len(), 8);
+    }
+
+    #[test]
+    fn shared_base_glyph_paint_becomes_colr_glyph_reference() {
+        let mut builder = LayerListBuilder::new(true);
+        let gid_a = GlyphId::new(4);
+        let gid_b = GlyphId::new(9);
+
+        let paint_a = builder.add_paint_layers(leaves(0..4));
+        let stored_a = builder.add_base_glyph(gid_a, paint_a.clone());
+        assert_eq!(stored_a, paint_a);
+
+        // glyph b builds the exact same layer sequence, so its layers are
+        // reused by add_paint_layers, and its base-glyph paint is then
+        // identical to glyph a's...
+        let paint_b = builder.add_paint_layers(leaves(0..4));
+        assert_eq!(paint_b, paint_a);
+
+        // ...so it should be replaced with a reference to glyph a.
+        let stored_b = builder.add_base_glyph(gid_b, paint_b);
+        assert_eq!(stored_b, Paint::colr_glyph(gid_a));
+    }
+
+    #[test]
+    fn distinct_base_glyph_paints_are_not_merged() {
+        let mut builder = LayerListBuilder::new(true);
+        let paint_a = builder.add_paint_layers(leaves(0..4));
+        let paint_b = builder.add_paint_layers(leaves(10..14));
+
+        let stored_a = builder.add_base_glyph(GlyphId::new(1), paint_a.clone());
+        let stored_b = builder.add_base_glyph(GlyphId::new(2), paint_b.clone());
+        assert_eq!(stored_a, paint_a);
+        assert_eq!(stored_b, paint_b);
+    }
+
+    #[test]
+    fn adjacent_glyphs_sharing_a_clip_box_are_coalesced() {
+        let box_a = ClipBox::new(0, 0, 100, 100);
+        let box_b = ClipBox::new(-10, -10, 110, 110);
+
+        let mut clip_boxes = BTreeMap::new();
+        clip_boxes.insert(GlyphId::new(1), box_a.clone());
+        clip_boxes.insert(GlyphId::new(2), box_a.clone());
+        clip_boxes.insert(GlyphId::new(3), box_a.clone());
+        // not adjacent to glyph 3 in id order, same box: still its own range
+        clip_boxes.insert(GlyphId::new(10), box_a.clone());
+        // adjacent to glyph 10, different box: new range
+        clip_boxes.insert(GlyphId::new(11), box_b.clone());
+
+        let clip_list = build_clip_list(&clip_boxes).unwrap();
+        assert_eq!(clip_list.clips.len(), 3);
+        assert_eq!(
+            clip_list.clips[0],
+            (GlyphId::new(1), GlyphId::new(3), box_a.clone())
+        );
+        assert_eq!(clip_list.clips[1], (GlyphId::new(10), GlyphId::new(10), box_a));
+        assert_eq!(clip_list.clips[2], (GlyphId::new(11), GlyphId::new(11), box_b));
+    }
+
+    #[test]
+    fn no_clip_boxes_means_no_clip_list() {
+        assert!(build_clip_list(&BTreeMap::new()).is_none());
+    }
+
+    #[test]
+    fn rolling_hash_window_matches_naive_hash() {
+        let ids = [3u32, 1, 4, 1, 5, 9, 2, 6];
+        let mut hashes = RollingHash::new();
+        hashes.extend(&ids);
+
+        // a window's hash is the naive polynomial hash of its contents
+        // ([1, 4] hashes to 1 * base + 4)...
+        assert_eq!(hashes.window_hash(1, 2), HASH_BASE + 4);
+        // ...([1, 4] at offset 1 and [1, 5] at offset 3 differ)...
+        assert_ne!(hashes.window_hash(1, 2), hashes.window_hash(3, 2));
+        // ...and distinct windows with the same contents must agree.
+        let ids2 = [1u32, 4];
+        let mut hashes2 = RollingHash::new();
+        hashes2.extend(&ids2);
+        assert_eq!(hashes.window_hash(1, 2), hashes2.window_hash(0, 2));
+    }
 }
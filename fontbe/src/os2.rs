@@ -1,19 +1,521 @@
 //! Generates a [OS/2](https://learn.microsoft.com/en-us/typography/opentype/spec/os2) table.
 
+use std::collections::BTreeSet;
+
 use fontdrasil::orchestration::Work;
-use fontir::ir::GlobalMetricsInstance;
-use read_fonts::{tables::hmtx::Hmtx, types::Tag, FontData, TopLevelTable};
-use write_fonts::{tables::os2::Os2, OtRound};
+use fontir::ir::{GlobalMetricsInstance, StaticMetadata};
+use read_fonts::{
+    tables::{
+        cmap::{Cmap, CmapSubtable},
+        gpos::{Gpos, PositioningLookup},
+        gsub::{Gsub, SubstitutionLookup},
+        hmtx::Hmtx,
+        layout::{ChainedSequenceContext, ChainedSequenceContextFormat3, SequenceContext},
+    },
+    types::Tag,
+    FontData, TopLevelTable,
+};
+use write_fonts::{
+    tables::{
+        head::MacStyle,
+        os2::{Os2, SelectionFlags},
+    },
+    OtRound,
+};
 
 use crate::{
     error::Error,
     orchestration::{BeWork, Context},
 };
 
-struct Os2Work {}
+/// Build-time knobs for OS/2 fields that aren't derived from the font sources.
+///
+/// Currently this is just `fsType`; as more such options accumulate they
+/// should live here rather than as ad-hoc extra arguments.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Os2BuildOptions {
+    /// Embedding permissions, written verbatim to `fsType`.
+    ///
+    /// 0 means "installable embedding", i.e. no restrictions.
+    pub fs_type: u16,
+}
+
+struct Os2Work {
+    build_options: Os2BuildOptions,
+}
 
 pub fn create_os2_work() -> Box<BeWork> {
-    Box::new(Os2Work {})
+    create_os2_work_with_options(Os2BuildOptions::default())
+}
+
+pub fn create_os2_work_with_options(build_options: Os2BuildOptions) -> Box<BeWork> {
+    Box::new(Os2Work { build_options })
+}
+
+/// The inclusive codepoint ranges that make up each `ulUnicodeRange` bit.
+///
+/// A single bit may cover more than one Unicode block, in which case it
+/// appears more than once in this table (e.g. bit 9, Cyrillic, also covers
+/// the Cyrillic Supplement and Extended blocks).
+///
+/// <https://learn.microsoft.com/en-us/typography/opentype/spec/os2#ulunicoderange1-bits-031ulunicoderange2-bits-3263ulunicoderange3-bits-6495ulunicoderange4-bits-96127>
+#[rustfmt::skip]
+const UNICODE_RANGES: &[(u8, u32, u32)] = &[
+    (0, 0x0000, 0x007F),   // Basic Latin
+    (1, 0x0080, 0x00FF),   // Latin-1 Supplement
+    (2, 0x0100, 0x017F),   // Latin Extended-A
+    (3, 0x0180, 0x024F),   // Latin Extended-B
+    (4, 0x0250, 0x02AF),   // IPA Extensions
+    (4, 0x1D00, 0x1D7F),   // Phonetic Extensions
+    (4, 0x1D80, 0x1DBF),   // Phonetic Extensions Supplement
+    (5, 0x02B0, 0x02FF),   // Spacing Modifier Letters
+    (5, 0xA700, 0xA71F),   // Modifier Tone Letters
+    (6, 0x0300, 0x036F),   // Combining Diacritical Marks
+    (6, 0x1DC0, 0x1DFF),   // Combining Diacritical Marks Supplement
+    (7, 0x0370, 0x03FF),   // Greek and Coptic
+    (8, 0x2C80, 0x2CFF),   // Coptic
+    (9, 0x0400, 0x04FF),   // Cyrillic
+    (9, 0x0500, 0x052F),   // Cyrillic Supplement
+    (9, 0x2DE0, 0x2DFF),   // Cyrillic Extended-A
+    (9, 0xA640, 0xA69F),   // Cyrillic Extended-B
+    (10, 0x0530, 0x058F),  // Armenian
+    (11, 0x0590, 0x05FF),  // Hebrew
+    (12, 0xA500, 0xA63F),  // Vai
+    (13, 0x0600, 0x06FF),  // Arabic
+    (13, 0x0750, 0x077F),  // Arabic Supplement
+    (14, 0x07C0, 0x07FF),  // NKo
+    (15, 0x0900, 0x097F),  // Devanagari
+    (16, 0x0980, 0x09FF),  // Bengali
+    (17, 0x0A00, 0x0A7F),  // Gurmukhi
+    (18, 0x0A80, 0x0AFF),  // Gujarati
+    (19, 0x0B00, 0x0B7F),  // Oriya
+    (20, 0x0B80, 0x0BFF),  // Tamil
+    (21, 0x0C00, 0x0C7F),  // Telugu
+    (22, 0x0C80, 0x0CFF),  // Kannada
+    (23, 0x0D00, 0x0D7F),  // Malayalam
+    (24, 0x0E00, 0x0E7F),  // Thai
+    (25, 0x0E80, 0x0EFF),  // Lao
+    (26, 0x10A0, 0x10FF),  // Georgian
+    (26, 0x2D00, 0x2D2F),  // Georgian Supplement
+    (27, 0x1B00, 0x1B7F),  // Balinese
+    (28, 0x1100, 0x11FF),  // Hangul Jamo
+    (29, 0x1E00, 0x1EFF),  // Latin Extended Additional
+    (29, 0x2C60, 0x2C7F),  // Latin Extended-C
+    (29, 0xA720, 0xA7FF),  // Latin Extended-D
+    (30, 0x1F00, 0x1FFF),  // Greek Extended
+    (31, 0x2000, 0x206F),  // General Punctuation
+    (31, 0x2E00, 0x2E7F),  // Supplemental Punctuation
+    (32, 0x2070, 0x209F),  // Superscripts And Subscripts
+    (33, 0x20A0, 0x20CF),  // Currency Symbols
+    (34, 0x20D0, 0x20FF),  // Combining Diacritical Marks For Symbols
+    (35, 0x2100, 0x214F),  // Letterlike Symbols
+    (36, 0x2150, 0x218F),  // Number Forms
+    (37, 0x2190, 0x21FF),  // Arrows
+    (37, 0x27F0, 0x27FF),  // Supplemental Arrows-A
+    (37, 0x2900, 0x297F),  // Supplemental Arrows-B
+    (37, 0x2B00, 0x2BFF),  // Miscellaneous Symbols and Arrows
+    (38, 0x2200, 0x22FF),  // Mathematical Operators
+    (38, 0x27C0, 0x27EF),  // Miscellaneous Mathematical Symbols-A
+    (38, 0x2980, 0x29FF),  // Miscellaneous Mathematical Symbols-B
+    (38, 0x2A00, 0x2AFF),  // Supplemental Mathematical Operators
+    (39, 0x2300, 0x23FF),  // Miscellaneous Technical
+    (40, 0x2400, 0x243F),  // Control Pictures
+    (41, 0x2440, 0x245F),  // Optical Character Recognition
+    (42, 0x2460, 0x24FF),  // Enclosed Alphanumerics
+    (43, 0x2500, 0x257F),  // Box Drawing
+    (44, 0x2580, 0x259F),  // Block Elements
+    (45, 0x25A0, 0x25FF),  // Geometric Shapes
+    (46, 0x2600, 0x26FF),  // Miscellaneous Symbols
+    (47, 0x2700, 0x27BF),  // Dingbats
+    (48, 0x3000, 0x303F),  // CJK Symbols And Punctuation
+    (49, 0x3040, 0x309F),  // Hiragana
+    (50, 0x30A0, 0x30FF),  // Katakana
+    (50, 0x31F0, 0x31FF),  // Katakana Phonetic Extensions
+    (51, 0x3100, 0x312F),  // Bopomofo
+    (51, 0x31A0, 0x31BF),  // Bopomofo Extended
+    (52, 0x3130, 0x318F),  // Hangul Compatibility Jamo
+    (53, 0xA840, 0xA87F),  // Phags-pa
+    (54, 0x3200, 0x32FF),  // Enclosed CJK Letters And Months
+    (55, 0x3300, 0x33FF),  // CJK Compatibility
+    (56, 0xAC00, 0xD7AF),  // Hangul Syllables
+    (57, 0xD800, 0xDFFF),  // Non-Plane 0 (surrogates)
+    (58, 0x10900, 0x1091F), // Phoenician
+    (59, 0x2E80, 0x2EFF),  // CJK Radicals Supplement
+    (59, 0x2F00, 0x2FDF),  // Kangxi Radicals
+    (59, 0x2FF0, 0x2FFF),  // Ideographic Description Characters
+    (59, 0x3190, 0x319F),  // Kanbun
+    (59, 0x3400, 0x4DBF),  // CJK Unified Ideographs Extension A
+    (59, 0x4E00, 0x9FFF),  // CJK Unified Ideographs
+    (59, 0x20000, 0x2A6DF), // CJK Unified Ideographs Extension B
+    (60, 0xE000, 0xF8FF),  // Private Use Area (plane 0)
+    (61, 0x31C0, 0x31EF),  // CJK Strokes
+    (61, 0xF900, 0xFAFF),  // CJK Compatibility Ideographs
+    (61, 0x2F800, 0x2FA1F), // CJK Compatibility Ideographs Supplement
+    (62, 0xFB00, 0xFB4F),  // Alphabetic Presentation Forms
+    (63, 0xFB50, 0xFDFF),  // Arabic Presentation Forms-A
+    (64, 0xFE20, 0xFE2F),  // Combining Half Marks
+    (65, 0xFE10, 0xFE1F),  // Vertical Forms
+    (65, 0xFE30, 0xFE4F),  // CJK Compatibility Forms
+    (66, 0xFE50, 0xFE6F),  // Small Form Variants
+    (67, 0xFE70, 0xFEFF),  // Arabic Presentation Forms-B
+    (68, 0xFF00, 0xFFEF),  // Halfwidth And Fullwidth Forms
+    (69, 0xFFF0, 0xFFFF),  // Specials
+    (70, 0x0F00, 0x0FFF),  // Tibetan
+    (71, 0x0700, 0x074F),  // Syriac
+    (72, 0x0780, 0x07BF),  // Thaana
+    (73, 0x0D80, 0x0DFF),  // Sinhala
+    (74, 0x1000, 0x109F),  // Myanmar
+    (75, 0x1200, 0x137F),  // Ethiopic
+    (75, 0x1380, 0x139F),  // Ethiopic Supplement
+    (75, 0x2D80, 0x2DDF),  // Ethiopic Extended
+    (76, 0x13A0, 0x13FF),  // Cherokee
+    (77, 0x1400, 0x167F),  // Unified Canadian Aboriginal Syllabics
+    (78, 0x1680, 0x169F),  // Ogham
+    (79, 0x16A0, 0x16FF),  // Runic
+    (80, 0x1780, 0x17FF),  // Khmer
+    (80, 0x19E0, 0x19FF),  // Khmer Symbols
+    (81, 0x1800, 0x18AF),  // Mongolian
+    (82, 0x2800, 0x28FF),  // Braille Patterns
+    (83, 0xA000, 0xA48F),  // Yi Syllables
+    (83, 0xA490, 0xA4CF),  // Yi Radicals
+    (84, 0x1700, 0x171F),  // Tagalog
+    (84, 0x1720, 0x173F),  // Hanunoo
+    (84, 0x1740, 0x175F),  // Buhid
+    (84, 0x1760, 0x177F),  // Tagbanwa
+    (85, 0x10300, 0x1032F), // Old Italic
+    (86, 0x10330, 0x1034F), // Gothic
+    (87, 0x10400, 0x1044F), // Deseret
+    (88, 0x1D000, 0x1D0FF), // Byzantine Musical Symbols
+    (88, 0x1D100, 0x1D1FF), // Musical Symbols
+    (88, 0x1D200, 0x1D24F), // Ancient Greek Musical Notation
+    (89, 0x1D400, 0x1D7FF), // Mathematical Alphanumeric Symbols
+    (90, 0xF0000, 0xFFFFD), // Private Use (plane 15)
+    (90, 0x100000, 0x10FFFD), // Private Use (plane 16)
+    (91, 0xFE00, 0xFE0F),  // Variation Selectors
+    (91, 0xE0100, 0xE01EF), // Variation Selectors Supplement
+    (92, 0xE0000, 0xE007F), // Tags
+    (93, 0x1900, 0x194F),  // Limbu
+    (94, 0x1950, 0x197F),  // Tai Le
+    (95, 0x1980, 0x19DF),  // New Tai Lue
+    (96, 0x1A00, 0x1A1F),  // Buginese
+    (97, 0x2C00, 0x2C5F),  // Glagolitic
+    (98, 0x2D30, 0x2D7F),  // Tifinagh
+    (99, 0x4DC0, 0x4DFF),  // Yijing Hexagram Symbols
+    (100, 0xA800, 0xA82F), // Syloti Nagri
+    (101, 0x10000, 0x1007F), // Linear B Syllabary
+    (101, 0x10080, 0x100FF), // Linear B Ideograms
+    (101, 0x10100, 0x1013F), // Aegean Numbers
+    (102, 0x10140, 0x1018F), // Ancient Greek Numbers
+    (103, 0x10380, 0x1039F), // Ugaritic
+    (104, 0x103A0, 0x103DF), // Old Persian
+    (105, 0x10450, 0x1047F), // Shavian
+    (106, 0x10480, 0x104AF), // Osmanya
+    (107, 0x10800, 0x1083F), // Cypriot Syllabary
+    (108, 0x10A00, 0x10A5F), // Kharoshthi
+    (109, 0x1D300, 0x1D35F), // Tai Xuan Jing Symbols
+    (110, 0x12000, 0x123FF), // Cuneiform
+    (110, 0x12400, 0x1247F), // Cuneiform Numbers and Punctuation
+    (111, 0x1D360, 0x1D37F), // Counting Rod Numerals
+    (112, 0x1B80, 0x1BBF), // Sundanese
+    (113, 0x1C00, 0x1C4F), // Lepcha
+    (114, 0x1C50, 0x1C7F), // Ol Chiki
+    (115, 0xA880, 0xA8DF), // Saurashtra
+    (116, 0xA900, 0xA92F), // Kayah Li
+    (117, 0xA930, 0xA95F), // Rejang
+    (118, 0xAA00, 0xAA5F), // Cham
+    (119, 0x10190, 0x101CF), // Ancient Symbols
+    (120, 0x101D0, 0x101FF), // Phaistos Disc
+    (121, 0x10280, 0x1029F), // Lycian
+    (121, 0x102A0, 0x102DF), // Carian
+    (121, 0x10920, 0x1093F), // Lydian
+    (122, 0x1F000, 0x1F02F), // Mahjong Tiles
+    (122, 0x1F030, 0x1F09F), // Domino Tiles
+];
+
+/// A small set of "signature" codepoints used to decide whether a font
+/// should claim support for a legacy `ulCodePageRange` code page.
+///
+/// A bit is only set if *every* codepoint in its signature set is present
+/// in the font's cmap; this mirrors how fontmake/fontTools approximate
+/// code-page coverage from Unicode cmap content alone.
+#[rustfmt::skip]
+const CODE_PAGE_SIGNATURES: &[(u8, &[u32])] = &[
+    (0, &[0x00E9, 0x00F1, 0x00FC]),          // Latin 1 / cp1252 (é, ñ, ü)
+    (1, &[0x0141, 0x0142, 0x0150]),          // Latin 2: Eastern Europe / cp1250 (Ł, ł, Ő)
+    (3, &[0x0410, 0x042F, 0x0450]),          // Cyrillic / cp1251 (А, Я, ѐ)
+    (4, &[0x011E, 0x0130, 0x015E]),          // Turkish / cp1254 (Ğ, İ, Ş)
+    (5, &[0x05D0, 0x05D1, 0x05EA]),          // Hebrew / cp1255
+    (6, &[0x0621, 0x0627, 0x064A]),          // Arabic / cp1256
+    (7, &[0x0100, 0x0101, 0x0123]),          // Windows Baltic / cp1257
+    (16, &[0x0E01, 0x0E2A, 0x0E4F]),         // Thai / cp874
+    (17, &[0x3042, 0x30A2, 0x4E00]),         // Japanese, JIS/Shift-JIS / cp932 (あ, ア, 一)
+    (18, &[0x4E2D, 0x56FD, 0x6587]),         // Chinese: Simplified / cp936 (中, 国, 文)
+    (19, &[0x3131, 0x314F, 0xAC00]),         // Korean Wansung / cp949
+    (20, &[0x4E2D, 0x570B, 0x6587]),         // Chinese: Traditional / cp950
+    (21, &[0x3131, 0x314F, 0xAC00]),         // Korean Johab / cp1361
+    (29, &[0x00C4, 0x00E4, 0x00F6]),         // Macintosh Character Set
+    (31, &[0x2022, 0x25CF, 0xF020]),         // Symbol Character Set
+    (62, &[0x00E9, 0x00F1, 0x00FC]),         // WE/Latin 1 / cp850
+    (63, &[0x0041, 0x005A, 0x007A]),         // US / cp437 (plain ASCII)
+];
+
+/// Collect every codepoint present in the font's (already compiled) cmap.
+fn all_codepoints(context: &Context) -> BTreeSet<u32> {
+    let raw_cmap = context.get_cmap();
+    let mut codepoints = BTreeSet::new();
+    let Ok(cmap) = Cmap::read(FontData::new(raw_cmap.get())) else {
+        return codepoints;
+    };
+    for record in cmap.encoding_records() {
+        let Ok(subtable) = record.subtable(cmap.offset_data()) else {
+            continue;
+        };
+        match subtable {
+            CmapSubtable::Format4(table) => {
+                let start_codes = table.start_code();
+                for (i, end) in table.end_code().iter().enumerate() {
+                    let end = end.get();
+                    let Some(start) = start_codes.get(i).map(|v| v.get()) else {
+                        continue;
+                    };
+                    if start <= end {
+                        codepoints.extend(start as u32..=end as u32);
+                    }
+                }
+            }
+            CmapSubtable::Format12(table) => {
+                for group in table.groups() {
+                    codepoints.extend(group.start_char_code()..=group.end_char_code());
+                }
+            }
+            _ => (),
+        }
+    }
+    codepoints
+}
+
+/// Compute `ulUnicodeRange1..4` from the set of codepoints covered by the cmap.
+fn unicode_range_bits(codepoints: &BTreeSet<u32>) -> (u32, u32, u32, u32) {
+    let mut bits: u128 = 0;
+    for cp in codepoints {
+        if let Some((bit, _, _)) = UNICODE_RANGES
+            .iter()
+            .find(|(_, start, end)| (*start..=*end).contains(cp))
+        {
+            bits |= 1 << bit;
+        }
+    }
+    (
+        bits as u32,
+        (bits >> 32) as u32,
+        (bits >> 64) as u32,
+        (bits >> 96) as u32,
+    )
+}
+
+/// Compute `ulCodePageRange1/2` from the set of codepoints covered by the cmap.
+fn code_page_range_bits(codepoints: &BTreeSet<u32>) -> (u32, u32) {
+    let mut bits: u64 = 0;
+    for (bit, signature) in CODE_PAGE_SIGNATURES {
+        if signature.iter().all(|cp| codepoints.contains(cp)) {
+            bits |= 1 << bit;
+        }
+    }
+    (bits as u32, (bits >> 32) as u32)
+}
+
+/// The context length of a chained (or plain) sequence context, i.e. the
+/// total number of glyphs a contextual/chaining lookup inspects:
+/// `backtrackGlyphCount + inputGlyphCount + lookaheadGlyphCount`.
+///
+/// Mirrors the three `SequenceContext`/`ChainedSequenceContext` subtable
+/// formats: per-glyph rule sets (format 1), per-class rule sets (format 2)
+/// and straight coverage-based rules (format 3).
+fn chained_context_max_context(context: &ChainedSequenceContext) -> u16 {
+    match context {
+        ChainedSequenceContext::Format1(t) => t
+            .chained_seq_rule_sets()
+            .iter()
+            .flatten()
+            .flat_map(|set| set.ok())
+            .flat_map(|set| set.chained_seq_rules().iter().flat_map(|r| r.ok()))
+            .map(|rule| {
+                (rule.backtrack_glyph_count() as u32
+                    + rule.input_glyph_count() as u32
+                    + rule.lookahead_glyph_count() as u32) as u16
+            })
+            .max()
+            .unwrap_or(0),
+        ChainedSequenceContext::Format2(t) => t
+            .chained_class_seq_rule_sets()
+            .iter()
+            .flatten()
+            .flat_map(|set| set.ok())
+            .flat_map(|set| set.chained_class_seq_rules().iter().flat_map(|r| r.ok()))
+            .map(|rule| {
+                (rule.backtrack_glyph_count() as u32
+                    + rule.input_glyph_count() as u32
+                    + rule.lookahead_glyph_count() as u32) as u16
+            })
+            .max()
+            .unwrap_or(0),
+        ChainedSequenceContext::Format3(t) => chained_format3_max_context(t),
+    }
+}
+
+fn chained_format3_max_context(t: &ChainedSequenceContextFormat3) -> u16 {
+    (t.backtrack_coverage_offsets().len()
+        + t.input_coverage_offsets().len()
+        + t.lookahead_coverage_offsets().len()) as u16
+}
+
+/// Same idea as [`chained_context_max_context`], but for the (non-chaining)
+/// `SequenceContext` formats used by plain contextual lookups (GSUB 5 /
+/// GPOS 7), which only ever inspect an input sequence.
+fn sequence_context_max_context(context: &SequenceContext) -> u16 {
+    match context {
+        SequenceContext::Format1(t) => t
+            .seq_rule_sets()
+            .iter()
+            .flatten()
+            .flat_map(|set| set.ok())
+            .flat_map(|set| set.seq_rules().iter().flat_map(|r| r.ok()))
+            .map(|rule| rule.glyph_count())
+            .max()
+            .unwrap_or(0),
+        SequenceContext::Format2(t) => t
+            .class_seq_rule_sets()
+            .iter()
+            .flatten()
+            .flat_map(|set| set.ok())
+            .flat_map(|set| set.class_seq_rules().iter().flat_map(|r| r.ok()))
+            .map(|rule| rule.glyph_count())
+            .max()
+            .unwrap_or(0),
+        SequenceContext::Format3(t) => t.coverage_offsets().len() as u16,
+    }
+}
+
+/// Context length contributed by a single GSUB lookup, per the rules in the
+/// `usMaxContext` spec: <https://learn.microsoft.com/en-us/typography/opentype/spec/os2#usmaxcontext>
+fn gsub_lookup_max_context(lookup: &SubstitutionLookup) -> u16 {
+    match lookup {
+        SubstitutionLookup::Single(_)
+        | SubstitutionLookup::Multiple(_)
+        | SubstitutionLookup::Alternate(_) => 1,
+        SubstitutionLookup::Ligature(t) => t
+            .subtables()
+            .iter()
+            .flat_map(|st| st.ok())
+            .flat_map(|st| st.ligature_sets().iter().flat_map(|set| set.ok()))
+            .flat_map(|set| set.ligatures().iter().flat_map(|lig| lig.ok()))
+            .map(|lig| 1 + lig.component_glyph_ids().len() as u16)
+            .max()
+            .unwrap_or(1),
+        SubstitutionLookup::Contextual(t) => t
+            .subtables()
+            .iter()
+            .flat_map(|st| st.ok())
+            .map(|st| sequence_context_max_context(&st))
+            .max()
+            .unwrap_or(0),
+        SubstitutionLookup::ChainContext(t) => t
+            .subtables()
+            .iter()
+            .flat_map(|st| st.ok())
+            .map(|st| chained_context_max_context(&st))
+            .max()
+            .unwrap_or(0),
+        SubstitutionLookup::ReverseChainContext(t) => t
+            .subtables()
+            .iter()
+            .flat_map(|st| st.ok())
+            .map(|st| {
+                1 + st.backtrack_coverage_offsets().len() as u16
+                    + st.lookahead_coverage_offsets().len() as u16
+            })
+            .max()
+            .unwrap_or(1),
+        // extension lookups just indirect to another (non-extension) lookup
+        SubstitutionLookup::Extension(t) => t
+            .subtables()
+            .iter()
+            .flat_map(|st| st.ok())
+            .flat_map(|st| st.extension())
+            .map(|inner| gsub_lookup_max_context(&inner))
+            .max()
+            .unwrap_or(0),
+    }
+}
+
+/// Context length contributed by a single GPOS lookup, per the rules in the
+/// `usMaxContext` spec.
+fn gpos_lookup_max_context(lookup: &PositioningLookup) -> u16 {
+    match lookup {
+        PositioningLookup::Single(_)
+        | PositioningLookup::Pair(_)
+        | PositioningLookup::Cursive(_)
+        | PositioningLookup::MarkToBase(_)
+        | PositioningLookup::MarkToLigature(_)
+        | PositioningLookup::MarkToMark(_) => 1,
+        PositioningLookup::Contextual(t) => t
+            .subtables()
+            .iter()
+            .flat_map(|st| st.ok())
+            .map(|st| sequence_context_max_context(&st))
+            .max()
+            .unwrap_or(0),
+        PositioningLookup::ChainContext(t) => t
+            .subtables()
+            .iter()
+            .flat_map(|st| st.ok())
+            .map(|st| chained_context_max_context(&st))
+            .max()
+            .unwrap_or(0),
+        PositioningLookup::Extension(t) => t
+            .subtables()
+            .iter()
+            .flat_map(|st| st.ok())
+            .flat_map(|st| st.extension())
+            .map(|inner| gpos_lookup_max_context(&inner))
+            .max()
+            .unwrap_or(0),
+    }
+}
+
+/// Compute `usMaxContext`: the largest number of glyphs any single lookup in
+/// the compiled GSUB/GPOS tables looks at when deciding whether to apply,
+/// so shapers can size their glyph-context buffers appropriately.
+fn max_context(context: &Context) -> u16 {
+    let mut max_context = 0;
+
+    if let Some(raw_gsub) = context.try_get_gsub() {
+        if let Ok(gsub) = Gsub::read(FontData::new(raw_gsub.get())) {
+            if let Ok(lookups) = gsub.lookup_list() {
+                max_context = lookups
+                    .lookups()
+                    .iter()
+                    .flat_map(|l| l.ok())
+                    .map(|l| gsub_lookup_max_context(&l))
+                    .fold(max_context, u16::max);
+            }
+        }
+    }
+
+    if let Some(raw_gpos) = context.try_get_gpos() {
+        if let Ok(gpos) = Gpos::read(FontData::new(raw_gpos.get())) {
+            if let Ok(lookups) = gpos.lookup_list() {
+                max_context = lookups
+                    .lookups()
+                    .iter()
+                    .flat_map(|l| l.ok())
+                    .map(|l| gpos_lookup_max_context(&l))
+                    .fold(max_context, u16::max);
+            }
+        }
+    }
+
+    max_context
 }
 
 /// <https://github.com/fonttools/fonttools/blob/115275cbf429d91b75ac5536f5f0b2d6fe9d823a/Lib/fontTools/ttLib/tables/O_S_2f_2.py#L336-L348>
@@ -56,21 +558,144 @@ fn x_avg_char_width(context: &Context) -> Result<i16, Error> {
     Ok((total as f32 / count as f32).ot_round())
 }
 
-fn build_os2(x_avg_char_width: i16, vendor_id: Tag, metrics: &GlobalMetricsInstance) -> Os2 {
+/// Round-trip a value that may be unset (zero) in the sources to a synthesized
+/// fallback computed from the units-per-em, the way fontmake/ufo2ft do when a
+/// UFO/Glyphs source doesn't specify explicit sub/superscript or strikeout
+/// metrics.
+fn or_synthesized(value: f64, fallback: f64) -> f64 {
+    if value == 0.0 {
+        fallback
+    } else {
+        value
+    }
+}
+
+/// `usWeightClass`/`usWidthClass` come from the `wght`/`wdth` axes (if present)
+/// at the default instance, the way a designspace's default master drives
+/// these fields when no override is present.
+fn weight_and_width_class(static_metadata: &StaticMetadata) -> (u16, u16) {
+    let wght = static_metadata
+        .axes
+        .iter()
+        .find(|axis| axis.tag == Tag::new(b"wght"))
+        .map(|axis| f64::from(axis.default))
+        .unwrap_or(400.0);
+    let wdth = static_metadata
+        .axes
+        .iter()
+        .find(|axis| axis.tag == Tag::new(b"wdth"))
+        .map(|axis| f64::from(axis.default))
+        .unwrap_or(100.0);
+
+    let us_width_class = width_class_for_percent(wdth);
+    let us_weight_class = wght.round().clamp(1.0, 1000.0) as u16;
+    (us_weight_class, us_width_class)
+}
+
+/// The `usWidthClass` 1..=9 scale's corresponding `wdth`-axis percentages, per
+/// the OpenType spec. The steps are uneven (12.5% up through class 7, then 25%
+/// and 50%), so classes are looked up by nearest match rather than computed
+/// from a fixed step size.
+const WIDTH_CLASS_PERCENTAGES: [f64; 9] =
+    [50.0, 62.5, 75.0, 87.5, 100.0, 112.5, 125.0, 150.0, 200.0];
+
+fn width_class_for_percent(wdth: f64) -> u16 {
+    WIDTH_CLASS_PERCENTAGES
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| (*a - wdth).abs().total_cmp(&(*b - wdth).abs()))
+        .map(|(i, _)| i as u16 + 1)
+        .unwrap()
+}
+
+fn fs_selection(mac_style: MacStyle) -> SelectionFlags {
+    let italic = mac_style.contains(MacStyle::ITALIC);
+    let bold = mac_style.contains(MacStyle::BOLD);
+
+    let mut fs_selection = SelectionFlags::USE_TYPO_METRICS;
+    if italic {
+        fs_selection |= SelectionFlags::ITALIC;
+    }
+    if bold {
+        fs_selection |= SelectionFlags::BOLD;
+    }
+    if !italic && !bold {
+        fs_selection |= SelectionFlags::REGULAR;
+    }
+    fs_selection
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_os2(
+    x_avg_char_width: i16,
+    vendor_id: Tag,
+    units_per_em: u16,
+    us_weight_class: u16,
+    us_width_class: u16,
+    metrics: &GlobalMetricsInstance,
+    codepoints: &BTreeSet<u32>,
+    mac_style: MacStyle,
+    us_max_context: u16,
+    build_options: Os2BuildOptions,
+) -> Os2 {
+    let (ul_unicode_range_1, ul_unicode_range_2, ul_unicode_range_3, ul_unicode_range_4) =
+        unicode_range_bits(codepoints);
+    let (ul_code_page_range_1, ul_code_page_range_2) = code_page_range_bits(codepoints);
+
+    let upm = units_per_em as f64;
+    let (us_first_char_index, us_last_char_index) = codepoints
+        .iter()
+        .copied()
+        .fold(None, |acc: Option<(u32, u32)>, cp| {
+            Some(acc.map_or((cp, cp), |(lo, hi)| (lo.min(cp), hi.max(cp))))
+        })
+        .map(|(lo, hi)| (lo.min(0xFFFF) as u16, hi.min(0xFFFF) as u16))
+        .unwrap_or((0, 0));
+
     Os2 {
         ach_vend_id: vendor_id,
 
         x_avg_char_width,
+        us_weight_class,
+        us_width_class,
+        fs_type: build_options.fs_type,
+        fs_selection: fs_selection(mac_style),
 
         s_cap_height: Some(metrics.cap_height.ot_round()),
         sx_height: Some(metrics.x_height.ot_round()),
 
+        y_subscript_x_size: or_synthesized(metrics.subscript_x_size, 0.65 * upm).ot_round(),
+        y_subscript_y_size: or_synthesized(metrics.subscript_y_size, 0.6 * upm).ot_round(),
+        y_subscript_x_offset: or_synthesized(metrics.subscript_x_offset, 0.0).ot_round(),
+        y_subscript_y_offset: or_synthesized(metrics.subscript_y_offset, 0.075 * upm).ot_round(),
+        y_superscript_x_size: or_synthesized(metrics.superscript_x_size, 0.65 * upm).ot_round(),
+        y_superscript_y_size: or_synthesized(metrics.superscript_y_size, 0.6 * upm).ot_round(),
+        y_superscript_x_offset: or_synthesized(metrics.superscript_x_offset, 0.0).ot_round(),
+        y_superscript_y_offset: or_synthesized(metrics.superscript_y_offset, 0.35 * upm)
+            .ot_round(),
+        y_strikeout_size: or_synthesized(metrics.strikeout_size, 0.05 * upm).ot_round(),
+        y_strikeout_position: or_synthesized(metrics.strikeout_position, 0.22 * upm).ot_round(),
+
+        s_typo_ascender: metrics.os2_typo_ascender.ot_round(),
+        s_typo_descender: metrics.os2_typo_descender.ot_round(),
+        s_typo_line_gap: metrics.os2_typo_line_gap.ot_round(),
+        us_win_ascent: metrics.os2_win_ascent.max(0.0).ot_round(),
+        us_win_descent: metrics.os2_win_descent.abs().ot_round(),
+
+        us_first_char_index,
+        us_last_char_index,
+
+        ul_unicode_range_1,
+        ul_unicode_range_2,
+        ul_unicode_range_3,
+        ul_unicode_range_4,
+
         // Avoid "field must be present for version 2"
-        ul_code_page_range_1: Some(0),
-        ul_code_page_range_2: Some(0),
+        ul_code_page_range_1: Some(ul_code_page_range_1),
+        ul_code_page_range_2: Some(ul_code_page_range_2),
         us_default_char: Some(0),
         us_break_char: Some(0),
-        us_max_context: Some(0),
+        us_max_context: Some(us_max_context),
 
         ..Default::default()
     }
@@ -84,11 +709,21 @@ impl Work<Context, Error> for Os2Work {
             .ir
             .get_global_metrics()
             .at(static_metadata.default_location());
+        let codepoints = all_codepoints(context);
+        let mac_style = context.get_head().mac_style;
+        let (us_weight_class, us_width_class) = weight_and_width_class(static_metadata);
 
         context.set_os2(build_os2(
             x_avg_char_width(context)?,
             static_metadata.vendor_id,
+            static_metadata.units_per_em,
+            us_weight_class,
+            us_width_class,
             &metrics,
+            &codepoints,
+            mac_style,
+            max_context(context),
+            self.build_options,
         ));
         Ok(())
     }
@@ -96,13 +731,19 @@ impl Work<Context, Error> for Os2Work {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeSet;
+
     use fontir::{
         coords::NormalizedLocation,
         ir::{GlobalMetric, GlobalMetrics},
     };
     use read_fonts::types::Tag;
+    use write_fonts::tables::{head::MacStyle, os2::SelectionFlags};
 
-    use super::build_os2;
+    use super::{
+        build_os2, code_page_range_bits, fs_selection, unicode_range_bits, width_class_for_percent,
+        Os2BuildOptions,
+    };
 
     #[test]
     fn build_basic_os2() {
@@ -112,11 +753,149 @@ mod tests {
         global_metrics.set(GlobalMetric::CapHeight, default_location.clone(), 37.5);
         global_metrics.set(GlobalMetric::XHeight, default_location.clone(), 112.2);
 
-        let os2 = build_os2(42, Tag::new(b"DUCK"), &global_metrics.at(&default_location));
+        let os2 = build_os2(
+            42,
+            Tag::new(b"DUCK"),
+            1000,
+            400,
+            5,
+            &global_metrics.at(&default_location),
+            &BTreeSet::new(),
+            MacStyle::empty(),
+            0,
+            Os2BuildOptions::default(),
+        );
 
         assert_eq!(Tag::new(b"DUCK"), os2.ach_vend_id);
         assert_eq!(42, os2.x_avg_char_width);
+        assert_eq!(400, os2.us_weight_class);
+        assert_eq!(5, os2.us_width_class);
         assert_eq!(Some(38), os2.s_cap_height);
         assert_eq!(Some(112), os2.sx_height);
+        assert!(os2.fs_selection.contains(SelectionFlags::REGULAR));
+    }
+
+    #[test]
+    fn width_class_follows_uneven_spec_scale() {
+        // the 7->8 and 8->9 steps are 25% and 50%, not the 12.5% step that
+        // governs the rest of the scale, so 150% ("Expanded") must land on
+        // class 8, not a linear-step-based class 9.
+        assert_eq!(width_class_for_percent(100.0), 5);
+        assert_eq!(width_class_for_percent(112.5), 6);
+        assert_eq!(width_class_for_percent(125.0), 7);
+        assert_eq!(width_class_for_percent(150.0), 8);
+        assert_eq!(width_class_for_percent(200.0), 9);
+    }
+
+    #[test]
+    fn fs_selection_bits_follow_mac_style() {
+        assert_eq!(
+            fs_selection(MacStyle::empty()),
+            SelectionFlags::USE_TYPO_METRICS | SelectionFlags::REGULAR
+        );
+        assert_eq!(
+            fs_selection(MacStyle::BOLD),
+            SelectionFlags::USE_TYPO_METRICS | SelectionFlags::BOLD
+        );
+        assert_eq!(
+            fs_selection(MacStyle::BOLD | MacStyle::ITALIC),
+            SelectionFlags::USE_TYPO_METRICS | SelectionFlags::BOLD | SelectionFlags::ITALIC
+        );
+    }
+
+    #[test]
+    fn unicode_range_basic_latin_and_cyrillic() {
+        let codepoints = BTreeSet::from([0x0041, 0x0410]); // 'A', Cyrillic А
+        let (bits1, bits2, bits3, bits4) = unicode_range_bits(&codepoints);
+        assert_eq!(bits1, (1 << 0) | (1 << 9));
+        assert_eq!((bits2, bits3, bits4), (0, 0, 0));
+    }
+
+    #[test]
+    fn unicode_range_supplementary_plane() {
+        // Deseret, U+10400, lives in a supplementary-plane block, bit 87,
+        // which falls in ulUnicodeRange3 (bits 64-95).
+        let codepoints = BTreeSet::from([0x10400]);
+        let (bits1, bits2, bits3, bits4) = unicode_range_bits(&codepoints);
+        assert_eq!((bits1, bits2, bits4), (0, 0, 0));
+        assert_eq!(bits3, 1 << (87 - 64));
+    }
+
+    #[test]
+    fn code_page_range_requires_full_signature() {
+        // Only part of the Latin-2 signature is present, so the bit must not be set.
+        let partial = BTreeSet::from([0x0141]);
+        assert_eq!(code_page_range_bits(&partial), (0, 0));
+
+        let full = BTreeSet::from([0x0141, 0x0142, 0x0150]);
+        let (bits1, _) = code_page_range_bits(&full);
+        assert_eq!(bits1, 1 << 1);
+    }
+
+    #[test]
+    fn first_and_last_char_index_clamp_supplementary_codepoints() {
+        // with only supplementary-plane codepoints (all > 0xFFFF), both
+        // usFirstCharIndex and usLastCharIndex must saturate to 0xFFFF
+        // rather than truncating/wrapping when cast down to u16.
+        let default_location = NormalizedLocation::new();
+        let global_metrics = GlobalMetrics::new(default_location.clone(), 1000);
+        let codepoints = BTreeSet::from([0x10400, 0x10428]); // Deseret, upper and lower case
+
+        let os2 = build_os2(
+            42,
+            Tag::new(b"DUCK"),
+            1000,
+            400,
+            5,
+            &global_metrics.at(&default_location),
+            &codepoints,
+            MacStyle::empty(),
+            0,
+            Os2BuildOptions::default(),
+        );
+
+        assert_eq!(os2.us_first_char_index, 0xFFFF);
+        assert_eq!(os2.us_last_char_index, 0xFFFF);
+    }
+
+    #[test]
+    fn code_page_range_latin1_and_cyrillic() {
+        let codepoints = BTreeSet::from([0x00E9, 0x00F1, 0x00FC, 0x0410, 0x042F, 0x0450]);
+        let (bits1, _) = code_page_range_bits(&codepoints);
+        assert_eq!(bits1, (1 << 0) | (1 << 3));
+    }
+
+    mod max_context {
+        use read_fonts::{tables::gsub::Gsub, FontData};
+        use write_fonts::tables::{
+            gsub::{
+                Gsub as WriteGsub, Ligature, LigatureSet, LigatureSubstFormat1,
+                LigatureSubstLookup, SubstitutionLookup as WriteLookup, SubstitutionLookupList,
+            },
+            layout::{FeatureList, ScriptList},
+        };
+
+        use super::super::gsub_lookup_max_context;
+
+        /// A ligature substituting N component glyphs (beyond the first) has
+        /// context length `1 + N`.
+        #[test]
+        fn ligature_max_context_is_component_count() {
+            let ligature = Ligature::new(1, vec![2.into(), 3.into()]);
+            let lig_set = LigatureSet::new(vec![ligature]);
+            let subtable = LigatureSubstFormat1::new(1.into(), vec![lig_set]);
+            let lookup = WriteLookup::Ligature(LigatureSubstLookup::new(vec![subtable]));
+
+            let gsub = WriteGsub::new(
+                ScriptList::default(),
+                FeatureList::default(),
+                SubstitutionLookupList::new(vec![lookup]),
+            );
+            let bytes = write_fonts::dump_table(&gsub).unwrap();
+            let gsub = Gsub::read(FontData::new(&bytes)).unwrap();
+            let lookup_list = gsub.lookup_list().unwrap();
+            let lookup = lookup_list.lookups().get(0).unwrap().unwrap();
+            assert_eq!(gsub_lookup_max_context(&lookup), 3);
+        }
     }
 }
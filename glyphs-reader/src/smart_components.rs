@@ -1,5 +1,7 @@
 use std::{
-    collections::{BTreeMap, HashMap},
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    rc::Rc,
     str::FromStr,
 };
 use thiserror::Error;
@@ -12,7 +14,7 @@ use fontdrasil::{
 use kurbo::{Affine, Vec2};
 use smol_str::SmolStr;
 
-use crate::{Component, Glyph, Layer, Node, Shape, font::AxisPole};
+use crate::{Component, Glyph, Layer, Node, Path, Shape, font::AxisPole};
 
 /// Things that can go wrong when instantiating a smart component
 #[derive(Debug, Error)]
@@ -25,24 +27,160 @@ pub enum BadSmartComponent {
         child: AxisPole,
         axis: SmolStr,
     },
+    #[error("component '{0}' does not reference a known glyph and layer")]
+    UnknownComponent(SmolStr),
+    #[error("smart component recursion limit exceeded while resolving '{0}'")]
+    RecursionOverflow(SmolStr),
+    #[error(
+        "incompatible masters for smart component '{glyph}': layer '{layer}' doesn't match \
+         '{base_layer}' at contour {contour}, node {node}"
+    )]
+    IncompatibleMasters {
+        glyph: SmolStr,
+        base_layer: SmolStr,
+        layer: SmolStr,
+        contour: usize,
+        node: usize,
+    },
 }
 
-/// Instantiate an instance of a smart component.
-///
-/// A smart component is a glyph that defines its own little variation space,
-/// such that specific instances of the glyph can be included as components of
-/// other glyphs.
+/// Instantiates smart components, caching the expensive part of the work.
 ///
-/// See <https://glyphsapp.com/learn/smart-components>.
+/// Building the variation model for a smart glyph's masters and deriving its
+/// deltas is identical every time that glyph is placed at a given master, so
+/// a font that places `_part.rectangle` a few hundred times would otherwise
+/// pay that cost a few hundred times. This caches that work, keyed by
+/// `(glyph name, layer_master_id)`, so that per-instance work reduces to
+/// normalizing the `smart_component_values` location and interpolating.
 ///
-/// This code is based on <https://github.com/googlefonts/glyphsLib/blob/52c982399b/Lib/glyphsLib/builder/smart_components.py#L96>
-pub(crate) fn instantiate_for_layer(
+/// Callers building a whole glyph set should hold a single instance of this
+/// across the build.
+#[derive(Default)]
+pub(crate) struct SmartComponentInstantiator {
+    cache: RefCell<HashMap<(SmolStr, SmolStr), Rc<CachedModel>>>,
+}
+
+/// The expensive, master-invariant part of instantiating a smart glyph: its
+/// base layer, the per-axis `(lower, default, upper)` tuples used
+/// to normalize raw component values, and the deltas derived from its
+/// variation model (wrapped in a closure so we don't need to name the
+/// model's own delta type).
+struct CachedModel {
+    base_layer: Layer,
+    axis_tuples: HashMap<SmolStr, (i64, i64, i64)>,
+    name_to_tag_map: BTreeMap<SmolStr, Tag>,
+    interpolate: Box<dyn Fn(&NormalizedLocation) -> Vec<Vec2>>,
+}
+
+impl SmartComponentInstantiator {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Instantiate an instance of a smart component.
+    ///
+    /// A smart component is a glyph that defines its own little variation
+    /// space, such that specific instances of the glyph can be included as
+    /// components of other glyphs.
+    ///
+    /// See <https://glyphsapp.com/learn/smart-components>.
+    ///
+    /// This code is based on <https://github.com/googlefonts/glyphsLib/blob/52c982399b/Lib/glyphsLib/builder/smart_components.py#L96>
+    pub(crate) fn instantiate_for_layer(
+        &self,
+        layer_master_id: &str,
+        component: &Component,
+        ref_glyph: &Glyph,
+        glyphs: &BTreeMap<SmolStr, Glyph>,
+        extrapolate: bool,
+    ) -> Result<Vec<Shape>, BadSmartComponent> {
+        let mut visited = HashSet::new();
+        visited.insert(ref_glyph.name.clone());
+        self.instantiate_for_layer_impl(
+            layer_master_id,
+            component,
+            ref_glyph,
+            glyphs,
+            extrapolate,
+            &mut visited,
+        )
+    }
+
+    fn instantiate_for_layer_impl(
+        &self,
+        layer_master_id: &str,
+        component: &Component,
+        ref_glyph: &Glyph,
+        glyphs: &BTreeMap<SmolStr, Glyph>,
+        extrapolate: bool,
+        visited: &mut HashSet<SmolStr>,
+    ) -> Result<Vec<Shape>, BadSmartComponent> {
+        assert!(!component.smart_component_values.is_empty());
+        assert!(!ref_glyph.smart_component_axes.is_empty());
+
+        let cached = self.get_or_build(layer_master_id, ref_glyph)?;
+
+        let location: NormalizedLocation = component
+            .smart_component_values
+            .iter()
+            .map(|(name, value)| {
+                (
+                    *cached.name_to_tag_map.get(name).unwrap(),
+                    normalize_value_with_extrapolation(
+                        *value,
+                        *cached.axis_tuples.get(name).unwrap(),
+                    ),
+                )
+            })
+            .collect();
+
+        log::debug!(
+            "instantiating component '{}' at {location:?}",
+            component.name
+        );
+
+        let eval = |loc: &NormalizedLocation| (cached.interpolate)(loc);
+        let points = if extrapolate {
+            extrapolate_points(&location, eval)
+        } else {
+            eval(&location)
+        };
+
+        let mut shapes = shapes_with_new_points(
+            &cached.base_layer,
+            &points,
+            layer_master_id,
+            self,
+            glyphs,
+            extrapolate,
+            visited,
+        )?;
+        shapes
+            .iter_mut()
+            .for_each(|shape| shape.apply_affine(component.transform));
+
+        Ok(shapes)
+    }
+
+    fn get_or_build(
+        &self,
+        layer_master_id: &str,
+        ref_glyph: &Glyph,
+    ) -> Result<Rc<CachedModel>, BadSmartComponent> {
+        let key = (ref_glyph.name.clone(), SmolStr::new(layer_master_id));
+        if let Some(cached) = self.cache.borrow().get(&key) {
+            return Ok(cached.clone());
+        }
+        let built = Rc::new(build_cached_model(layer_master_id, ref_glyph)?);
+        self.cache.borrow_mut().insert(key, built.clone());
+        Ok(built)
+    }
+}
+
+fn build_cached_model(
     layer_master_id: &str,
-    component: &Component,
     ref_glyph: &Glyph,
-) -> Result<Vec<Shape>, BadSmartComponent> {
-    assert!(!component.smart_component_values.is_empty());
-    assert!(!ref_glyph.smart_component_axes.is_empty());
+) -> Result<CachedModel, BadSmartComponent> {
     let (axis_order, name_to_tag_map) = axes_for_glyph(ref_glyph);
 
     // these are the layers of the glyph that have the same associated master
@@ -55,22 +193,6 @@ pub(crate) fn instantiate_for_layer(
         })
         .collect::<Vec<_>>();
 
-    if relevant_layers.len() == 1 {
-        log::debug!("smart component {} only has one layer?", component.name);
-        let mut shapes = relevant_layers[0].shapes.clone();
-        shapes
-            .iter_mut()
-            .for_each(|shape| shape.apply_affine(component.transform));
-        return Ok(shapes);
-    }
-
-    let locations = relevant_layers
-        .iter()
-        .map(|layer| normalized_location(layer, relevant_layers[0], &name_to_tag_map))
-        .collect::<Result<_, _>>()?;
-
-    let model = VariationModel::new(locations, axis_order.clone());
-
     let axis_tuples = ref_glyph
         .smart_component_axes
         .iter()
@@ -85,25 +207,35 @@ pub(crate) fn instantiate_for_layer(
             } else {
                 *range.end()
             };
-            (name, (*range.start(), default_value, *range.end()))
+            (name.clone(), (*range.start(), default_value, *range.end()))
         })
         .collect::<HashMap<_, _>>();
 
-    let location: NormalizedLocation = component
-        .smart_component_values
+    if relevant_layers.len() == 1 {
+        log::debug!("smart component {} only has one layer?", ref_glyph.name);
+        let fixed_points = relevant_layers[0]
+            .shapes
+            .iter()
+            .filter_map(Shape::as_path)
+            .flat_map(|path| path.nodes.iter().map(|node| node.pt))
+            .map(Vec2::from)
+            .collect::<Vec<_>>();
+        return Ok(CachedModel {
+            base_layer: relevant_layers[0].clone(),
+            axis_tuples,
+            name_to_tag_map,
+            interpolate: Box::new(move |_| fixed_points.clone()),
+        });
+    }
+
+    check_layer_compatibility(&ref_glyph.name, &relevant_layers)?;
+
+    let locations = relevant_layers
         .iter()
-        .map(|(name, value)| {
-            (
-                *name_to_tag_map.get(name).unwrap(),
-                normalize_value_with_extrapolation(*value, *axis_tuples.get(name).unwrap()),
-            )
-        })
-        .collect();
+        .map(|layer| normalized_location(layer, relevant_layers[0], &name_to_tag_map))
+        .collect::<Result<_, _>>()?;
 
-    log::debug!(
-        "instantiating component '{}' at {location:?}",
-        component.name
-    );
+    let model = VariationModel::new(locations, axis_order);
 
     let point_seqs = relevant_layers
         .iter()
@@ -119,13 +251,79 @@ pub(crate) fn instantiate_for_layer(
         })
         .collect::<Result<HashMap<_, _>, BadSmartComponent>>()?;
     let deltas = model.deltas(&point_seqs).unwrap();
-    let points = VariationModel::interpolate_from_deltas(&location, &deltas);
-    let mut shapes = shapes_with_new_points(relevant_layers[0], &points);
-    shapes
-        .iter_mut()
-        .for_each(|shape| shape.apply_affine(component.transform));
 
-    Ok(shapes)
+    Ok(CachedModel {
+        base_layer: relevant_layers[0].clone(),
+        axis_tuples,
+        name_to_tag_map,
+        interpolate: Box::new(move |loc| VariationModel::interpolate_from_deltas(loc, &deltas)),
+    })
+}
+
+// `VariationModel` only interpolates within each axis' normalized [-1, 1]
+// range, but `normalize_value_with_extrapolation` can hand us coordinates
+// outside it when a smart component is pushed past its outermost masters.
+// The model is piecewise-affine between masters, so the exact linear
+// continuation past the boundary can be recovered without knowing anything
+// about the model's internals: sample two points along the same direction as
+// `location`'s out-of-range axes, both still inside the outermost affine
+// region, and extend the line they describe out to `location` itself.
+//
+// Only the axes that are actually out of range are moved when probing; every
+// in-range axis is held fixed at its real, queried coordinate. This keeps the
+// extrapolation exact even for a "combination" master whose contribution is a
+// product of per-axis scalars (one in-range axis times one out-of-range
+// axis): since the in-range axis' scalar is constant across the probe, the
+// sampled values are still affine in the out-of-range axis alone. The one
+// case this doesn't cover is a combination master where *multiple* axes are
+// simultaneously out of range at once - there, each axis' scalar continuation
+// multiplies the others', which is quadratic (or higher) rather than affine
+// along the shared probe direction, and this still approximates it linearly.
+fn extrapolate_points(
+    location: &NormalizedLocation,
+    eval: impl Fn(&NormalizedLocation) -> Vec<Vec2>,
+) -> Vec<Vec2> {
+    const PROBE: f64 = 1e-3;
+
+    let clamp_factor = location
+        .iter()
+        .map(|(_, coord)| coord.to_f64().abs())
+        .filter(|v| *v > 1.0)
+        .fold(1.0_f64, |acc, v| acc.min(1.0 / v));
+
+    if clamp_factor >= 1.0 {
+        return eval(location);
+    }
+
+    let boundary = scale_out_of_range_axes(location, clamp_factor);
+    let inward = scale_out_of_range_axes(location, clamp_factor * (1.0 - PROBE));
+
+    let at_boundary = eval(&boundary);
+    let near_boundary = eval(&inward);
+    let t = 1.0 / clamp_factor;
+
+    at_boundary
+        .iter()
+        .zip(&near_boundary)
+        .map(|(far, near)| *far + (*far - *near) / PROBE * (t - 1.0))
+        .collect()
+}
+
+/// Scale only the axes of `location` that are currently out of the
+/// normalized `[-1, 1]` range by `factor`, leaving in-range axes untouched.
+fn scale_out_of_range_axes(location: &NormalizedLocation, factor: f64) -> NormalizedLocation {
+    location
+        .iter()
+        .map(|(tag, coord)| {
+            let value = coord.to_f64();
+            let scaled = if value.abs() > 1.0 {
+                value * factor
+            } else {
+                value
+            };
+            (*tag, NormalizedCoord::new(scaled))
+        })
+        .collect()
 }
 
 // component parts just have names, not tags, but VariationModel needs tags;
@@ -147,7 +345,64 @@ fn axes_for_glyph(glyph: &Glyph) -> (Vec<Tag>, BTreeMap<SmolStr, Tag>) {
     (axis_order, name_to_tag_map)
 }
 
-fn shapes_with_new_points(layer: &Layer, points: &[Vec2]) -> Vec<Shape> {
+// `instantiate_for_layer` flattens every relevant layer's paths into a bare
+// `Vec<Vec2>` and zips them positionally, so masters that disagree on
+// contour count, node count, or node type would otherwise produce garbage
+// (or panic) instead of a clear error.
+fn check_layer_compatibility(
+    glyph_name: &SmolStr,
+    relevant_layers: &[&Layer],
+) -> Result<(), BadSmartComponent> {
+    let base = relevant_layers[0];
+    let base_paths = base
+        .shapes
+        .iter()
+        .filter_map(Shape::as_path)
+        .collect::<Vec<_>>();
+
+    let mismatch =
+        |layer: &Layer, contour: usize, node: usize| BadSmartComponent::IncompatibleMasters {
+            glyph: glyph_name.clone(),
+            base_layer: base.layer_id.clone(),
+            layer: layer.layer_id.clone(),
+            contour,
+            node,
+        };
+
+    for layer in &relevant_layers[1..] {
+        let paths = layer
+            .shapes
+            .iter()
+            .filter_map(Shape::as_path)
+            .collect::<Vec<_>>();
+        if paths.len() != base_paths.len() {
+            return Err(mismatch(layer, base_paths.len().min(paths.len()), 0));
+        }
+        for (contour, (base_path, path)) in base_paths.iter().zip(&paths).enumerate() {
+            if base_path.closed != path.closed || base_path.nodes.len() != path.nodes.len() {
+                return Err(mismatch(layer, contour, 0));
+            }
+            for (node, (base_node, this_node)) in
+                base_path.nodes.iter().zip(&path.nodes).enumerate()
+            {
+                if base_node.node_type != this_node.node_type {
+                    return Err(mismatch(layer, contour, node));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn shapes_with_new_points(
+    layer: &Layer,
+    points: &[Vec2],
+    layer_master_id: &str,
+    instantiator: &SmartComponentInstantiator,
+    glyphs: &BTreeMap<SmolStr, Glyph>,
+    extrapolate: bool,
+    visited: &mut HashSet<SmolStr>,
+) -> Result<Vec<Shape>, BadSmartComponent> {
     let mut points = points;
     layer
         .shapes
@@ -159,12 +414,104 @@ fn shapes_with_new_points(layer: &Layer, points: &[Vec2]) -> Vec<Shape> {
                     node.pt = newpt.to_point();
                 }
                 points = &points[path.nodes.len()..];
-                Shape::Path(path)
+                Ok(vec![Shape::Path(path)])
             }
-            // we just skip components, which matches fonttools. Should we error instead?
-            Shape::Component(_) => shape.clone(),
+            Shape::Component(inner) => resolve_nested_component(
+                inner,
+                layer_master_id,
+                instantiator,
+                glyphs,
+                extrapolate,
+                visited,
+            ),
         })
-        .collect()
+        .collect::<Result<Vec<_>, _>>()
+        .map(|nested| nested.into_iter().flatten().collect())
+}
+
+// a `_part.*` glyph can itself contain ordinary components, or even other
+// smart components, nested inside its layers. Those need to be resolved
+// against the same master/location and flattened into plain paths, the way
+// a composite-glyph outline builder descends into sub-glyphs, rather than
+// left as opaque references.
+fn resolve_shapes(
+    shapes: &[Shape],
+    layer_master_id: &str,
+    instantiator: &SmartComponentInstantiator,
+    glyphs: &BTreeMap<SmolStr, Glyph>,
+    extrapolate: bool,
+    visited: &mut HashSet<SmolStr>,
+) -> Result<Vec<Shape>, BadSmartComponent> {
+    shapes
+        .iter()
+        .map(|shape| match shape {
+            Shape::Path(_) => Ok(vec![shape.clone()]),
+            Shape::Component(inner) => resolve_nested_component(
+                inner,
+                layer_master_id,
+                instantiator,
+                glyphs,
+                extrapolate,
+                visited,
+            ),
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(|nested| nested.into_iter().flatten().collect())
+}
+
+fn resolve_nested_component(
+    inner: &Component,
+    layer_master_id: &str,
+    instantiator: &SmartComponentInstantiator,
+    glyphs: &BTreeMap<SmolStr, Glyph>,
+    extrapolate: bool,
+    visited: &mut HashSet<SmolStr>,
+) -> Result<Vec<Shape>, BadSmartComponent> {
+    let child_glyph = glyphs
+        .get(&inner.name)
+        .ok_or_else(|| BadSmartComponent::UnknownComponent(inner.name.clone()))?;
+
+    if visited.contains(&child_glyph.name) {
+        return Err(BadSmartComponent::RecursionOverflow(inner.name.clone()));
+    }
+
+    let mut shapes = if !child_glyph.smart_component_axes.is_empty()
+        && !inner.smart_component_values.is_empty()
+    {
+        visited.insert(child_glyph.name.clone());
+        let result = instantiator.instantiate_for_layer_impl(
+            layer_master_id,
+            inner,
+            child_glyph,
+            glyphs,
+            extrapolate,
+            visited,
+        );
+        visited.remove(&child_glyph.name);
+        result?
+    } else {
+        let layer = child_glyph
+            .layers
+            .iter()
+            .find(|layer| layer.master_id() == layer_master_id)
+            .ok_or_else(|| BadSmartComponent::UnknownComponent(inner.name.clone()))?;
+        visited.insert(child_glyph.name.clone());
+        let result = resolve_shapes(
+            &layer.shapes,
+            layer_master_id,
+            instantiator,
+            glyphs,
+            extrapolate,
+            visited,
+        );
+        visited.remove(&child_glyph.name);
+        result?
+    };
+
+    shapes
+        .iter_mut()
+        .for_each(|shape| shape.apply_affine(inner.transform));
+    Ok(shapes)
 }
 
 //https://github.com/fonttools/fonttools/blob/03a3c8ed9e/Lib/fontTools/varLib/models.py#L47
@@ -435,12 +782,12 @@ mod tests {
                 [("Width", 0.5), ("Height", 300.0), ("Shift", -50.0)].as_slice(),
                 (50.0, 50.0, 300.0, 300.0),
             ),
-            // Extrapolation
-            // NOTE: this currently fails. Does our variation model support extrapolation?
-            //(
-            //[("Width", 0.0), ("Height", 800.0), ("Shift", 0.0)].as_slice(),
-            //(100.0, 100.0, 100.0, 800.0),
-            //),
+            // Extrapolation: a value past the outermost "tall" master
+            // continues linearly rather than snapping back to the default.
+            (
+                [("Width", 0.0), ("Height", 800.0), ("Shift", 0.0)].as_slice(),
+                (100.0, 100.0, 100.0, 800.0),
+            ),
         ];
 
         let glyphs = smart_glyphs(master_id);
@@ -457,7 +804,9 @@ mod tests {
                 .collect();
 
             let rectangle = glyphs.get(&SmolStr::new("_part.rectangle")).unwrap();
-            let shapes = instantiate_for_layer(master_id, &modified_component, rectangle)
+            let instantiator = SmartComponentInstantiator::new();
+            let shapes = instantiator
+                .instantiate_for_layer(master_id, &modified_component, rectangle, &glyphs, true)
                 .expect("instantiate should succeed");
 
             let (rect, dir) = get_rectangle_data(&shapes[0]);
@@ -473,4 +822,212 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn extrapolation_holds_in_range_axes_fixed_for_combination_master() {
+        // models a "combination master" surface where two axes interact
+        // multiplicatively: `a*10 + b*20 + a*b*70`. This isn't affine along a
+        // ray through the origin (scaling both axes together), but *is*
+        // affine in `b` alone for any fixed `a` - exactly what a genuine
+        // combination master contributes via one in-range axis times one
+        // out-of-range axis. If extrapolation scaled `a` along with `b` (as
+        // a naive ray-through-the-origin approach would), this would not
+        // come out exact.
+        let tag_a = Tag::new(b"ax00");
+        let tag_b = Tag::new(b"ax01");
+        let eval = |loc: &NormalizedLocation| {
+            let value = |tag| {
+                loc.iter()
+                    .find_map(|(t, coord)| (*t == tag).then(|| coord.to_f64()))
+                    .unwrap()
+            };
+            let (a, b) = (value(tag_a), value(tag_b));
+            vec![Vec2::new(a * 10.0 + b * 20.0 + a * b * 70.0, 0.0)]
+        };
+
+        // `a` stays in range at 0.3; `b` is pushed to 2.5, past the
+        // outermost master at `b == 1.0`.
+        let location: NormalizedLocation = [
+            (tag_a, NormalizedCoord::new(0.3)),
+            (tag_b, NormalizedCoord::new(2.5)),
+        ]
+        .into_iter()
+        .collect();
+
+        let points = extrapolate_points(&location, eval);
+
+        let expected = 0.3 * 10.0 + 2.5 * 20.0 + 0.3 * 2.5 * 70.0;
+        assert!(
+            (points[0].x - expected).abs() < 1e-6,
+            "{} != {expected}",
+            points[0].x
+        );
+    }
+
+    #[test]
+    fn nested_plain_component_is_flattened_with_composed_transform() {
+        let master_id = "master01";
+        let mut glyphs = smart_glyphs(master_id);
+        let rectangle_name = glyphs
+            .get(&SmolStr::new("_part.rectangle"))
+            .unwrap()
+            .name
+            .clone();
+
+        let nested_component = || {
+            Shape::Component(Component {
+                name: rectangle_name.clone(),
+                transform: Affine::translate((50.0, 0.0)),
+                ..Default::default()
+            })
+        };
+
+        let mut carrier = Glyph {
+            name: "_part.carrier".into(),
+            ..Default::default()
+        };
+        carrier
+            .smart_component_axes
+            .insert(SmolStr::new("Width"), 0..=1);
+        carrier.layers.push(Layer {
+            layer_id: master_id.into(),
+            shapes: vec![nested_component()],
+            smart_component_positions: [(SmolStr::new("Width"), AxisPole::Min)]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        });
+        carrier.layers.push(Layer {
+            layer_id: "carrier_wide".into(),
+            associated_master_id: Some(master_id.into()),
+            shapes: vec![nested_component()],
+            smart_component_positions: [(SmolStr::new("Width"), AxisPole::Max)]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        });
+        glyphs.insert(carrier.name.clone(), carrier.clone());
+
+        let component = Component {
+            name: carrier.name.clone(),
+            transform: Affine::translate((0.0, 5.0)),
+            smart_component_values: BTreeMap::from([(SmolStr::new("Width"), 0.5)]),
+            ..Default::default()
+        };
+
+        let instantiator = SmartComponentInstantiator::new();
+        let shapes = instantiator
+            .instantiate_for_layer(master_id, &component, &carrier, &glyphs, true)
+            .expect("instantiate should succeed");
+
+        let (rect, _) = get_rectangle_data(&shapes[0]);
+        // the nested rectangle's "regular" layer (100,100,100,100), shifted by
+        // the nested component's own transform (+50, +0) and then the outer
+        // carrier component's transform (+0, +5).
+        assert_eq!(rect, Rect::new(150.0, 105.0, 250.0, 205.0));
+    }
+
+    #[test]
+    fn nested_component_cycle_is_rejected() {
+        let master_id = "master01";
+        let mut glyphs: BTreeMap<SmolStr, Glyph> = BTreeMap::new();
+
+        let mut cyclic = Glyph {
+            name: "_part.cyclic".into(),
+            ..Default::default()
+        };
+        cyclic
+            .smart_component_axes
+            .insert(SmolStr::new("Width"), 0..=1);
+        let self_reference = |pole| Layer {
+            layer_id: if pole == AxisPole::Min {
+                master_id.into()
+            } else {
+                "cyclic_wide".into()
+            },
+            associated_master_id: (pole != AxisPole::Min).then(|| master_id.into()),
+            shapes: vec![Shape::Component(Component {
+                name: "_part.cyclic".into(),
+                smart_component_values: BTreeMap::from([(SmolStr::new("Width"), 0.5)]),
+                ..Default::default()
+            })],
+            smart_component_positions: [(SmolStr::new("Width"), pole)].into_iter().collect(),
+            ..Default::default()
+        };
+        cyclic.layers.push(self_reference(AxisPole::Min));
+        cyclic.layers.push(self_reference(AxisPole::Max));
+        glyphs.insert(cyclic.name.clone(), cyclic.clone());
+
+        let component = Component {
+            name: cyclic.name.clone(),
+            smart_component_values: BTreeMap::from([(SmolStr::new("Width"), 0.5)]),
+            ..Default::default()
+        };
+
+        let instantiator = SmartComponentInstantiator::new();
+        let err = instantiator
+            .instantiate_for_layer(master_id, &component, &cyclic, &glyphs, true)
+            .expect_err("cyclic component reference should be rejected");
+        assert!(matches!(err, BadSmartComponent::RecursionOverflow(_)));
+    }
+
+    #[test]
+    fn cached_model_is_reused_across_calls() {
+        let master_id = "master01";
+        let glyphs = smart_glyphs(master_id);
+        let rectangle = glyphs.get(&SmolStr::new("_part.rectangle")).unwrap();
+
+        let instantiator = SmartComponentInstantiator::new();
+        let first = instantiator.get_or_build(master_id, rectangle).unwrap();
+        let second = instantiator.get_or_build(master_id, rectangle).unwrap();
+
+        // a second call for the same (glyph, master) must hand back the
+        // exact same cached model rather than rebuilding it from scratch.
+        assert!(
+            Rc::ptr_eq(&first, &second),
+            "second get_or_build rebuilt the model instead of reusing the cache"
+        );
+        assert_eq!(instantiator.cache.borrow().len(), 1);
+    }
+
+    #[test]
+    fn incompatible_masters_are_rejected() {
+        let master_id = "master01";
+        let mut glyphs = smart_glyphs(master_id);
+
+        // drop a node from the "wide" master so it no longer matches the
+        // base layer's contour.
+        let rectangle = glyphs.get_mut(&SmolStr::new("_part.rectangle")).unwrap();
+        let wide = rectangle
+            .layers
+            .iter_mut()
+            .find(|layer| layer.layer_id == "wide")
+            .unwrap();
+        let Shape::Path(path) = &mut wide.shapes[0] else {
+            panic!("expected a path");
+        };
+        path.nodes.pop();
+
+        let rectangle = glyphs
+            .get(&SmolStr::new("_part.rectangle"))
+            .unwrap()
+            .clone();
+        let component = Component {
+            name: rectangle.name.clone(),
+            smart_component_values: BTreeMap::from([(SmolStr::new("Width"), 1.0)]),
+            ..Default::default()
+        };
+
+        let instantiator = SmartComponentInstantiator::new();
+        let err = instantiator
+            .instantiate_for_layer(master_id, &component, &rectangle, &glyphs, true)
+            .expect_err("mismatched master node count should be rejected");
+        match err {
+            BadSmartComponent::IncompatibleMasters { layer, contour, .. } => {
+                assert_eq!(layer, "wide");
+                assert_eq!(contour, 0);
+            }
+            other => panic!("expected IncompatibleMasters, got {other:?}"),
+        }
+    }
 }